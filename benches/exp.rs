@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use evm::{Capture, ExitSucceed, Machine, Shared};
+
+/// Code running `EXP` with exponent 2 many times, as fixed-point math does.
+fn small_exp_code() -> Vec<u8> {
+	let mut code = Vec::new();
+	for _ in 0..1000 {
+		// PUSH1 2 PUSH32 base EXP POP
+		code.extend_from_slice(&[0x60, 0x02, 0x7f]);
+		code.extend_from_slice(&[0xab; 32]);
+		code.extend_from_slice(&[0x0a, 0x50]);
+	}
+	code
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+	let code = Shared::new(small_exp_code());
+	let data = Shared::new(Vec::new());
+
+	c.bench_function("small exp", |b| {
+		b.iter(|| {
+			let mut machine = Machine::new(code.clone(), data.clone(), 1024, 10000);
+			assert_eq!(machine.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+		})
+	});
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
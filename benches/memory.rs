@@ -0,0 +1,23 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use evm::Memory;
+
+const SIZE: usize = 1024 * 1024;
+
+/// Write one word at a time, growing memory by 32 bytes on each write, as a
+/// loop of `MSTORE`s over fresh memory does.
+fn fill(mut memory: Memory) -> Memory {
+	for offset in (0..SIZE).step_by(32) {
+		memory.set(offset, &[0xff; 32], None).unwrap();
+	}
+	memory
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+	c.bench_function("memory grow", |b| b.iter(|| fill(Memory::new(SIZE))));
+	c.bench_function("memory grow presized", |b| {
+		b.iter(|| fill(Memory::with_capacity(SIZE, SIZE)))
+	});
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,88 @@
+use crate::{Opcode, Valids};
+use alloc::vec::Vec;
+
+/// Partition of code into basic blocks. A block starts at the beginning of
+/// the code, at every valid `JUMPDEST`, and right after every opcode that
+/// ends one (see `Opcode::is_terminating`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlocks {
+	starts: Vec<usize>,
+	code_len: usize,
+}
+
+impl BasicBlocks {
+	/// Compute the basic blocks of given code bytes.
+	pub fn new(code: &[u8]) -> Self {
+		let valids = Valids::new(code);
+		let mut starts = Vec::new();
+
+		let mut i = 0;
+		let mut ended = true;
+		while i < code.len() {
+			if ended || valids.is_valid(i) {
+				starts.push(i);
+			}
+
+			let opcode = Opcode(code[i]);
+			ended = opcode.is_terminating();
+			i += opcode.immediate_size() + 1;
+		}
+
+		Self {
+			starts,
+			code_len: code.len(),
+		}
+	}
+
+	/// Number of blocks.
+	pub fn len(&self) -> usize {
+		self.starts.len()
+	}
+
+	/// Returns true if there are no blocks, which is only the case for empty
+	/// code.
+	pub fn is_empty(&self) -> bool {
+		self.starts.is_empty()
+	}
+
+	/// Start positions of the blocks, in order.
+	pub fn starts(&self) -> &[usize] {
+		&self.starts
+	}
+
+	/// Index of the block containing `position`, or `None` if it is past the
+	/// end of the code. Push data belongs to the block of its push opcode.
+	pub fn block_of(&self, position: usize) -> Option<usize> {
+		if position >= self.code_len {
+			return None;
+		}
+
+		Some(self.starts.partition_point(|start| *start <= position) - 1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::BasicBlocks;
+
+	#[test]
+	fn test_basic_blocks() {
+		// 0: PUSH1 0x5b  2: JUMPI  3: PUSH1 8  5: JUMP  6: JUMPDEST  7: STOP
+		// 8: JUMPDEST  9: STOP
+		let code = [0x60, 0x5b, 0x57, 0x60, 0x08, 0x56, 0x5b, 0x00, 0x5b, 0x00];
+		let blocks = BasicBlocks::new(&code);
+
+		assert_eq!(blocks.starts(), &[0, 3, 6, 8]);
+		assert_eq!(blocks.block_of(0), Some(0));
+		// The 0x5b in push data is not a block start.
+		assert_eq!(blocks.block_of(1), Some(0));
+		assert_eq!(blocks.block_of(2), Some(0));
+		assert_eq!(blocks.block_of(5), Some(1));
+		assert_eq!(blocks.block_of(7), Some(2));
+		assert_eq!(blocks.block_of(9), Some(3));
+		assert_eq!(blocks.block_of(10), None);
+
+		assert!(BasicBlocks::new(&[]).is_empty());
+		assert_eq!(BasicBlocks::new(&[]).block_of(0), None);
+	}
+}
@@ -14,6 +14,26 @@ pub enum Capture<E, T> {
 	Trap(T),
 }
 
+impl<E, T> Capture<E, T> {
+	/// Whether the machine has exited.
+	pub fn is_exit(&self) -> bool {
+		matches!(self, Self::Exit(_))
+	}
+
+	/// Whether the machine has trapped.
+	pub fn is_trap(&self) -> bool {
+		matches!(self, Self::Trap(_))
+	}
+
+	/// Convert into a `Result`, with the trap as the error.
+	pub fn into_result(self) -> Result<E, T> {
+		match self {
+			Self::Exit(e) => Ok(e),
+			Self::Trap(t) => Err(t),
+		}
+	}
+}
+
 /// Exit reason.
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(
@@ -69,6 +89,9 @@ pub enum ExitSucceed {
 	Returned,
 	/// Machine encountered an explicit suicide.
 	Suicided,
+	/// Machine ran past the end of the code without an explicit halt. Only
+	/// reported if enabled with `Machine::set_report_out_of_code`.
+	OutOfCode,
 }
 
 impl From<ExitSucceed> for ExitReason {
@@ -139,6 +162,8 @@ pub enum ExitError {
 
 	/// Other normal errors.
 	Other(Cow<'static, str>),
+	/// Encountered an opcode that is not defined.
+	InvalidOpcode,
 }
 
 impl From<ExitError> for ExitReason {
@@ -73,6 +73,15 @@ pub fn mulmod(op1: U256, op2: U256, op3: U256) -> U256 {
 
 #[inline]
 pub fn exp(op1: U256, op2: U256) -> U256 {
+	// Small exponents are common in fixed-point math; skip the loop for them.
+	if op2 <= U256::from(2) {
+		return match op2.low_u64() {
+			0 => U256::one(),
+			1 => op1,
+			_ => op1.overflowing_mul(op1).0,
+		};
+	}
+
 	let mut op1 = op1;
 	let mut op2 = op2;
 	let mut r: U256 = 1.into();
@@ -122,7 +131,7 @@ pub fn signextend(op1: U256, op2: U256) -> U256 {
 
 #[cfg(test)]
 mod tests {
-	use super::{signextend, U256};
+	use super::{exp, signextend, U256};
 
 	/// Test to ensure new (optimized) `signextend` implementation is equivalent to the previous
 	/// implementation.
@@ -176,4 +185,40 @@ mod tests {
 			ret
 		}
 	}
+
+	/// Test to ensure the small exponent fast path of `exp` matches the general
+	/// square-and-multiply loop.
+	#[test]
+	fn test_exp() {
+		let bases = [
+			U256::zero(),
+			U256::one(),
+			U256::from(2),
+			U256::from(10),
+			U256::one() << 128,
+			U256::MAX / 3,
+			U256::MAX,
+		];
+		for base in bases.iter() {
+			for power in 0..8 {
+				assert_eq!(exp(*base, power.into()), old_exp(*base, power.into()));
+			}
+		}
+	}
+
+	fn old_exp(op1: U256, op2: U256) -> U256 {
+		let mut op1 = op1;
+		let mut op2 = op2;
+		let mut r: U256 = 1.into();
+
+		while op2 != 0.into() {
+			if op2 & 1.into() != 0.into() {
+				r = r.overflowing_mul(op1).0;
+			}
+			op2 >>= 1;
+			op1 = op1.overflowing_mul(op1).0;
+		}
+
+		r
+	}
 }
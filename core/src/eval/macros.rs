@@ -117,18 +117,16 @@ macro_rules! op3_u256_fn {
 
 macro_rules! as_usize_or_fail {
 	( $v:expr ) => {{
-		if $v > U256::from(usize::MAX) {
-			return Control::Exit(ExitFatal::NotSupported.into());
+		match crate::as_usize($v) {
+			Ok(v) => v,
+			Err(_) => return Control::Exit(crate::ExitFatal::NotSupported.into()),
 		}
-
-		$v.as_usize()
 	}};
 
 	( $v:expr, $reason:expr ) => {{
-		if $v > U256::from(usize::MAX) {
-			return Control::Exit($reason.into());
+		match crate::as_usize($v) {
+			Ok(v) => v,
+			Err(_) => return Control::Exit($reason.into()),
 		}
-
-		$v.as_usize()
 	}};
 }
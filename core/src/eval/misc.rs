@@ -1,6 +1,5 @@
 use super::Control;
-use crate::{ExitError, ExitFatal, ExitRevert, ExitSucceed, Machine};
-use core::cmp::min;
+use crate::{ExitError, ExitRevert, ExitSucceed, Machine, Opcode, ReturnRange};
 use primitive_types::{H256, U256};
 
 #[inline]
@@ -139,6 +138,41 @@ pub fn jumpi(state: &mut Machine) -> Control {
 	}
 }
 
+#[cfg(feature = "eof")]
+fn relative_jump_target(state: &Machine, position: usize) -> Option<usize> {
+	let hi = *state.code.get(position + 1).unwrap_or(&0);
+	let lo = *state.code.get(position + 2).unwrap_or(&0);
+	let offset = i16::from_be_bytes([hi, lo]) as isize;
+
+	let target = ((position + 3) as isize).checked_add(offset)?;
+	if target < 0 || !state.valids.is_instruction(target as usize) {
+		return None;
+	}
+
+	Some(target as usize)
+}
+
+#[cfg(feature = "eof")]
+#[inline]
+pub fn rjump(state: &mut Machine, position: usize) -> Control {
+	match relative_jump_target(state, position) {
+		Some(target) => Control::Jump(target),
+		None => Control::Exit(ExitError::InvalidJump.into()),
+	}
+}
+
+#[cfg(feature = "eof")]
+#[inline]
+pub fn rjumpi(state: &mut Machine, position: usize) -> Control {
+	pop_u256!(state, value);
+
+	if value != U256::zero() {
+		rjump(state, position)
+	} else {
+		Control::Continue(3)
+	}
+}
+
 #[inline]
 pub fn pc(state: &mut Machine, position: usize) -> Control {
 	push_u256!(state, U256::from(position));
@@ -153,14 +187,19 @@ pub fn msize(state: &mut Machine) -> Control {
 
 #[inline]
 pub fn push(state: &mut Machine, n: usize, position: usize) -> Control {
-	let end = min(position + 1 + n, state.code.len());
-	let slice = &state.code[(position + 1)..end];
-	let val = U256::from_big_endian(slice);
+	// Only dispatched for push opcodes, so there is always an immediate.
+	let val = Opcode::read_immediate(&state.code, position).unwrap_or_default();
 
 	push_u256!(state, val);
 	Control::Continue(1 + n)
 }
 
+#[inline]
+pub fn push0(state: &mut Machine) -> Control {
+	push_u256!(state, U256::zero());
+	Control::Continue(1)
+}
+
 #[inline]
 pub fn push1(state: &mut Machine, position: usize) -> Control {
 	let b0 = *state.code.get(position + 1).unwrap_or(&0) as u64;
@@ -214,15 +253,23 @@ pub fn swap(state: &mut Machine, n: usize) -> Control {
 #[inline]
 pub fn ret(state: &mut Machine) -> Control {
 	pop_u256!(state, start, len);
+	let range = match ReturnRange::new(start, len) {
+		Some(range) => range,
+		None => return Control::Exit(ExitError::OutOfOffset.into()),
+	};
 	try_or_fail!(state.memory.resize_offset(start, len));
-	state.return_range = start..(start + len);
+	state.return_range = Some(range);
 	Control::Exit(ExitSucceed::Returned.into())
 }
 
 #[inline]
 pub fn revert(state: &mut Machine) -> Control {
 	pop_u256!(state, start, len);
+	let range = match ReturnRange::new(start, len) {
+		Some(range) => range,
+		None => return Control::Exit(ExitError::OutOfOffset.into()),
+	};
 	try_or_fail!(state.memory.resize_offset(start, len));
-	state.return_range = start..(start + len);
+	state.return_range = Some(range);
 	Control::Exit(ExitRevert::Reverted.into())
 }
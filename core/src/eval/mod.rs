@@ -4,18 +4,40 @@ mod arithmetic;
 mod bitwise;
 mod misc;
 
-use crate::{ExitError, ExitReason, ExitSucceed, Machine, Opcode};
+use crate::{ExitError, ExitReason, ExitSucceed, Machine, Opcode, OpcodeFn};
 use core::ops::{BitAnd, BitOr, BitXor};
 use primitive_types::U256;
 
+/// What the machine does after evaluating an opcode.
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum Control {
+	/// Move on by the given number of bytes.
 	Continue(usize),
+	/// Stop with the given reason.
 	Exit(ExitReason),
+	/// Jump to the given position, which must already be validated.
 	Jump(usize),
+	/// Trap to the caller with the given opcode.
 	Trap(Opcode),
 }
 
+/// Custom opcode evaluations, indexed by opcode byte. Opcodes without an
+/// entry use the built-in table.
+pub type ExternalTable = [Option<OpcodeFn>; 256];
+
+/// Register `f` as the evaluation of `opcode` in `table`, replacing any earlier
+/// entry. Install the table with `Machine::set_external_table`.
+///
+/// `f` runs after the fork and base gas checks, with the program counter
+/// still on the opcode. It pops its inputs from and pushes its outputs onto
+/// the stack, and returns `Control::Continue(n)` to move on by `n` bytes (1
+/// for an opcode without immediates), `Control::Jump` to a position that is
+/// a valid jump destination, `Control::Exit` to stop the machine, or
+/// `Control::Trap` to hand the opcode to the caller.
+pub fn register_external(table: &mut ExternalTable, opcode: Opcode, f: OpcodeFn) {
+	table[opcode.as_usize()] = Some(f);
+}
+
 fn eval_stop(_state: &mut Machine, _opcode: Opcode, _position: usize) -> Control {
 	Control::Exit(ExitSucceed::Stopped.into())
 }
@@ -176,6 +198,10 @@ fn eval_jumpdest(_state: &mut Machine, _opcode: Opcode, _position: usize) -> Con
 	Control::Continue(1)
 }
 
+fn eval_push0(state: &mut Machine, _opcode: Opcode, _position: usize) -> Control {
+	self::misc::push0(state)
+}
+
 fn eval_push1(state: &mut Machine, _opcode: Opcode, position: usize) -> Control {
 	self::misc::push1(state, position)
 }
@@ -432,6 +458,16 @@ fn eval_swap16(state: &mut Machine, _opcode: Opcode, _position: usize) -> Contro
 	self::misc::swap(state, 16)
 }
 
+#[cfg(feature = "eof")]
+fn eval_rjump(state: &mut Machine, _opcode: Opcode, position: usize) -> Control {
+	self::misc::rjump(state, position)
+}
+
+#[cfg(feature = "eof")]
+fn eval_rjumpi(state: &mut Machine, _opcode: Opcode, position: usize) -> Control {
+	self::misc::rjumpi(state, position)
+}
+
 fn eval_return(state: &mut Machine, _opcode: Opcode, _position: usize) -> Control {
 	self::misc::ret(state)
 }
@@ -494,6 +530,7 @@ pub fn eval(state: &mut Machine, opcode: Opcode, position: usize) -> Control {
 		table[Opcode::MSIZE.as_usize()] = eval_msize as _;
 		table[Opcode::JUMPDEST.as_usize()] = eval_jumpdest as _;
 
+		table[Opcode::PUSH0.as_usize()] = eval_push0 as _;
 		table[Opcode::PUSH1.as_usize()] = eval_push1 as _;
 		table[Opcode::PUSH2.as_usize()] = eval_push2 as _;
 		table[Opcode::PUSH3.as_usize()] = eval_push3 as _;
@@ -561,6 +598,12 @@ pub fn eval(state: &mut Machine, opcode: Opcode, position: usize) -> Control {
 		table[Opcode::SWAP15.as_usize()] = eval_swap15 as _;
 		table[Opcode::SWAP16.as_usize()] = eval_swap16 as _;
 
+		#[cfg(feature = "eof")]
+		{
+			table[Opcode::RJUMP.as_usize()] = eval_rjump as _;
+			table[Opcode::RJUMPI.as_usize()] = eval_rjumpi as _;
+		}
+
 		table[Opcode::RETURN.as_usize()] = eval_return as _;
 		table[Opcode::REVERT.as_usize()] = eval_revert as _;
 		table[Opcode::INVALID.as_usize()] = eval_invalid as _;
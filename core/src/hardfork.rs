@@ -0,0 +1,43 @@
+/// Ethereum hard forks that changed the set of available opcodes, in
+/// activation order. Forks that only changed gas costs or other rules are
+/// listed so that a chain's fork can be named directly.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Hardfork {
+	/// Frontier.
+	Frontier,
+	/// Homestead, adding `DELEGATECALL`.
+	Homestead,
+	/// Tangerine Whistle.
+	TangerineWhistle,
+	/// Spurious Dragon.
+	SpuriousDragon,
+	/// Byzantium, adding `REVERT`, `RETURNDATASIZE`, `RETURNDATACOPY` and
+	/// `STATICCALL`.
+	Byzantium,
+	/// Constantinople, adding `SHL`, `SHR`, `SAR`, `CREATE2` and
+	/// `EXTCODEHASH`.
+	Constantinople,
+	/// Petersburg.
+	Petersburg,
+	/// Istanbul, adding `CHAINID` and `SELFBALANCE`.
+	Istanbul,
+	/// Berlin.
+	Berlin,
+	/// London, adding `BASEFEE`.
+	London,
+	/// Shanghai, adding `PUSH0`.
+	Shanghai,
+}
+
+impl Hardfork {
+	/// The most recent supported fork.
+	pub const fn latest() -> Self {
+		Hardfork::Shanghai
+	}
+}
+
+impl Default for Hardfork {
+	fn default() -> Self {
+		Self::latest()
+	}
+}
@@ -7,42 +7,87 @@
 extern crate alloc;
 extern crate core;
 
+mod basic_blocks;
 mod error;
 mod eval;
+mod hardfork;
 mod memory;
 mod opcode;
+mod return_range;
 mod stack;
 mod utils;
 mod valids;
 
+pub use crate::basic_blocks::BasicBlocks;
 pub use crate::error::{Capture, ExitError, ExitFatal, ExitReason, ExitRevert, ExitSucceed, Trap};
-pub use crate::memory::Memory;
+pub use crate::eval::{register_external, Control, ExternalTable};
+pub use crate::hardfork::Hardfork;
+pub use crate::memory::{Memory, MemoryBudget};
 pub use crate::opcode::Opcode;
+pub use crate::return_range::ReturnRange;
 pub use crate::stack::Stack;
+pub use crate::utils::{as_usize, decode_revert_reason, memory_gas_cost, u256_byte_len};
 pub use crate::valids::Valids;
 
-use crate::eval::{eval, Control};
-use alloc::rc::Rc;
+/// Shared pointer holding code and data. With the `threadsafe` feature this is
+/// `Arc`, which makes `Machine` `Send` and `Sync` so machines can run on other
+/// threads over the same bytecode; otherwise it is `Rc`.
+#[cfg(not(feature = "threadsafe"))]
+pub use alloc::rc::Rc as Shared;
+/// Shared pointer holding code and data. With the `threadsafe` feature this is
+/// `Arc`, which makes `Machine` `Send` and `Sync` so machines can run on other
+/// threads over the same bytecode; otherwise it is `Rc`.
+#[cfg(feature = "threadsafe")]
+pub use alloc::sync::Arc as Shared;
+
+use crate::eval::eval;
+use alloc::boxed::Box;
 use alloc::vec::Vec;
-use core::ops::Range;
-use primitive_types::U256;
+use primitive_types::{H256, U256};
+use sha3::{Digest, Keccak256};
+
+/// Evaluation of a custom opcode, see `Machine::register_opcode`.
+pub type OpcodeFn = fn(&mut Machine) -> Control;
 
 /// Core execution layer for EVM.
 pub struct Machine {
 	/// Program data.
-	data: Rc<Vec<u8>>,
+	data: Shared<Vec<u8>>,
 	/// Program code.
-	code: Rc<Vec<u8>>,
+	code: Shared<Vec<u8>>,
+	/// Keccak-256 hash of the code, once computed.
+	code_hash: Option<H256>,
 	/// Program counter.
 	position: Result<usize, ExitReason>,
-	/// Return value.
-	return_range: Range<U256>,
+	/// Return value. `None` until `RETURN` or `REVERT` sets it.
+	return_range: Option<ReturnRange>,
 	/// Code validity maps.
 	valids: Valids,
 	/// Memory.
 	memory: Memory,
 	/// Stack.
 	stack: Stack,
+	/// Whether unknown opcodes trap rather than exit.
+	trap_on_unknown: bool,
+	/// Whether running off the end of the code exits with `OutOfCode` rather
+	/// than `Stopped`.
+	report_out_of_code: bool,
+	/// Hard fork whose opcodes are available.
+	fork: Hardfork,
+	/// Gas left, if the machine meters itself.
+	gas: Option<u64>,
+	/// Opcode counts, if profiling.
+	profile: Option<Box<Profile>>,
+	/// Which positions have run, if recording coverage.
+	coverage: Option<Vec<bool>>,
+	/// Custom opcodes, indexed by opcode byte, if any are registered.
+	custom_opcodes: Option<Box<ExternalTable>>,
+}
+
+/// Per-opcode counts, indexed by opcode byte.
+struct Profile {
+	steps: [u64; 256],
+	traps: [u64; 256],
 }
 
 impl Machine {
@@ -62,15 +107,41 @@ impl Machine {
 	pub fn memory_mut(&mut self) -> &mut Memory {
 		&mut self.memory
 	}
+	/// Reference of machine code.
+	pub fn code(&self) -> &[u8] {
+		&self.code
+	}
+	/// Positions of all valid jump destinations in the code, in order.
+	pub fn jumpdests(&self) -> Vec<usize> {
+		(0..self.valids.len())
+			.filter(|position| self.valids.is_valid(*position))
+			.collect()
+	}
+	/// Keccak-256 hash of the machine code, as `EXTCODEHASH` would report it.
+	/// The code never changes, so it is hashed on the first call only.
+	pub fn code_keccak(&mut self) -> H256 {
+		let code = &self.code;
+		*self
+			.code_hash
+			.get_or_insert_with(|| H256::from_slice(Keccak256::digest(&code[..]).as_slice()))
+	}
 	/// Return a reference of the program counter.
 	pub fn position(&self) -> &Result<usize, ExitReason> {
 		&self.position
 	}
+	/// Whether the machine has not exited yet.
+	pub fn is_running(&self) -> bool {
+		self.position.is_ok()
+	}
+	/// Program counter, if the machine has not exited yet.
+	pub fn pc(&self) -> Option<usize> {
+		self.position.as_ref().ok().copied()
+	}
 
 	/// Create a new machine with given code and data.
 	pub fn new(
-		code: Rc<Vec<u8>>,
-		data: Rc<Vec<u8>>,
+		code: Shared<Vec<u8>>,
+		data: Shared<Vec<u8>>,
 		stack_limit: usize,
 		memory_limit: usize,
 	) -> Self {
@@ -79,12 +150,226 @@ impl Machine {
 		Self {
 			data,
 			code,
+			code_hash: None,
 			position: Ok(0),
-			return_range: U256::zero()..U256::zero(),
+			return_range: None,
 			valids,
 			memory: Memory::new(memory_limit),
 			stack: Stack::new(stack_limit),
+			trap_on_unknown: true,
+			report_out_of_code: false,
+			fork: Hardfork::latest(),
+			gas: None,
+			profile: None,
+			coverage: None,
+			custom_opcodes: None,
+		}
+	}
+
+	/// Evaluate `opcode` with `f` instead of the built-in table. Unlike a trap,
+	/// this needs no handler, which suits custom opcode families such as the
+	/// `0xb0` range some L2s use. `f` finds the position with `pc` and returns
+	/// how to move on, usually `Control::Continue(1)`. Fork availability and
+	/// gas are checked before `f` runs, as for any other opcode.
+	pub fn register_opcode(&mut self, opcode: Opcode, f: OpcodeFn) {
+		let table = self
+			.custom_opcodes
+			.get_or_insert_with(|| Box::new([None; 256]));
+		register_external(table, opcode, f);
+	}
+
+	/// Replace all custom opcodes with `table`, built with `register_external`.
+	/// This lets one table be shared by every machine of an embedder.
+	pub fn set_external_table(&mut self, table: ExternalTable) {
+		self.custom_opcodes = Some(Box::new(table));
+	}
+
+	/// Create a machine with empty code and data, for tests or as a
+	/// placeholder frame. It exits on its first step.
+	pub fn empty(stack_limit: usize, memory_limit: usize) -> Self {
+		Self::new(
+			Shared::new(Vec::new()),
+			Shared::new(Vec::new()),
+			stack_limit,
+			memory_limit,
+		)
+	}
+
+	/// Only allow the opcodes available in `fork`. Others exit with
+	/// `ExitError::InvalidOpcode` before they run. By default every opcode of
+	/// the latest fork is available.
+	pub fn with_fork(mut self, fork: Hardfork) -> Self {
+		self.fork = fork;
+		self
+	}
+
+	/// Create a new machine with values already on the stack, pushed in order
+	/// so the last one ends up on top. Fails with `StackOverflow` if they do
+	/// not fit in `stack_limit`.
+	pub fn with_stack(
+		code: Shared<Vec<u8>>,
+		data: Shared<Vec<u8>>,
+		stack: Vec<U256>,
+		stack_limit: usize,
+		memory_limit: usize,
+	) -> Result<Self, ExitError> {
+		if stack.len() > stack_limit {
+			return Err(ExitError::StackOverflow);
+		}
+
+		let mut machine = Self::new(code, data, stack_limit, memory_limit);
+		for value in stack {
+			machine.stack.push(value)?;
+		}
+
+		Ok(machine)
+	}
+
+	/// Create a new machine, reading its code from `reader` until end of
+	/// input. The buffer is moved into the shared pointer, so the code is not
+	/// copied after reading.
+	#[cfg(feature = "std")]
+	pub fn from_reader<R: std::io::Read>(
+		mut reader: R,
+		data: Shared<Vec<u8>>,
+		stack_limit: usize,
+		memory_limit: usize,
+	) -> std::io::Result<Self> {
+		let mut code = Vec::new();
+		reader.read_to_end(&mut code)?;
+
+		Ok(Self::new(
+			Shared::new(code),
+			data,
+			stack_limit,
+			memory_limit,
+		))
+	}
+
+	/// Set whether opcodes the machine does not implement trap, which is the
+	/// default, or exit with `ExitError::InvalidOpcode`. Only turn trapping off
+	/// when the machine runs on its own: the runtime relies on traps to
+	/// implement system opcodes such as `CALL`.
+	pub fn set_trap_on_unknown(&mut self, trap_on_unknown: bool) {
+		self.trap_on_unknown = trap_on_unknown;
+	}
+
+	/// Set whether running off the end of the code exits with
+	/// `ExitSucceed::OutOfCode` instead of the default `ExitSucceed::Stopped`,
+	/// to tell an implicit halt from an explicit `STOP` when debugging.
+	pub fn set_report_out_of_code(&mut self, report_out_of_code: bool) {
+		self.report_out_of_code = report_out_of_code;
+	}
+
+	/// Meter execution in the machine itself, starting with `gas`. Each step
+	/// then deducts `Opcode::base_gas` before running the opcode, and exits
+	/// with `ExitError::OutOfGas` if there is not enough left. Dynamic costs
+	/// are not charged; use a handler-driven gasometer for a full schedule.
+	pub fn set_gas(&mut self, gas: u64) {
+		self.gas = Some(gas);
+	}
+
+	/// Gas left, or `None` if the machine does not meter itself.
+	pub fn gas_left(&self) -> Option<u64> {
+		self.gas
+	}
+
+	/// Charge memory growth of this machine to `budget` as well, so that the
+	/// machines of a call tree can share a total cap. See
+	/// `Memory::set_budget`.
+	pub fn set_memory_budget(&mut self, budget: Shared<MemoryBudget>) -> Result<(), ExitError> {
+		self.memory.set_budget(budget)
+	}
+
+	/// Start counting executed opcodes, for `step_profile` and
+	/// `trap_profile`. Counts start from zero.
+	pub fn enable_profile(&mut self) {
+		self.profile = Some(Box::new(Profile {
+			steps: [0; 256],
+			traps: [0; 256],
+		}));
+	}
+
+	/// Number of times each opcode was dispatched, indexed by opcode byte.
+	/// This includes opcodes that trapped. All zero unless `enable_profile`
+	/// was called.
+	pub fn step_profile(&self) -> [u64; 256] {
+		self.profile.as_ref().map(|p| p.steps).unwrap_or([0; 256])
+	}
+
+	/// Number of times each opcode trapped, indexed by opcode byte. These are
+	/// the external opcodes left to the caller, such as the runtime. All zero
+	/// unless `enable_profile` was called.
+	pub fn trap_profile(&self) -> [u64; 256] {
+		self.profile.as_ref().map(|p| p.traps).unwrap_or([0; 256])
+	}
+
+	/// The `n` opcodes dispatched most often, with their counts from
+	/// `step_profile`. Opcodes that never ran are left out, and equal counts
+	/// are ordered by opcode byte, lowest first, so reports are reproducible.
+	pub fn top_opcodes(&self, n: usize) -> Vec<(Opcode, u64)> {
+		let mut counts: Vec<(Opcode, u64)> = self
+			.step_profile()
+			.iter()
+			.enumerate()
+			.filter(|(_, count)| **count > 0)
+			.map(|(byte, count)| (Opcode(byte as u8), *count))
+			.collect();
+		counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.as_u8().cmp(&b.0.as_u8())));
+		counts.truncate(n);
+		counts
+	}
+
+	/// Start recording which positions of the code run, for `covered_pcs`.
+	/// Coverage starts empty.
+	pub fn enable_coverage(&mut self) {
+		self.coverage = Some(alloc::vec![false; self.code.len()]);
+	}
+
+	/// Whether each position of the code has run, indexed by position, such
+	/// as for coverage-guided fuzzing. Positions of opcodes that were
+	/// dispatched count, including ones that failed or trapped; push data
+	/// never does. Empty unless `enable_coverage` was called.
+	pub fn covered_pcs(&self) -> &[bool] {
+		self.coverage.as_deref().unwrap_or(&[])
+	}
+
+	/// Replace the program data. The code and its jump destination map are
+	/// kept, so together with `reset` this reruns the same code on new input.
+	pub fn set_data(&mut self, data: Shared<Vec<u8>>) {
+		self.data = data;
+	}
+
+	/// Reset the machine to the start of the code, with empty stack and memory
+	/// and no return value. Code, data, limits and the memory budget are kept.
+	/// Fails with `InvalidRange` if the budget cannot be kept.
+	pub fn reset(&mut self) -> Result<(), ExitError> {
+		self.position = Ok(0);
+		self.return_range = None;
+		let budget = self.memory.budget().cloned();
+		self.memory = Memory::new(self.memory.limit());
+		self.stack = Stack::new(self.stack.limit());
+		if let Some(budget) = budget {
+			self.memory.set_budget(budget)?;
 		}
+		Ok(())
+	}
+
+	/// Set the return value to `data`, as `RETURN` would. The data is written to
+	/// memory past its current end, so existing contents are kept. Follow this
+	/// with `exit` to make the machine return.
+	pub fn set_return(&mut self, data: &[u8]) -> Result<(), ExitError> {
+		let start = self.memory.effective_len();
+		let len = U256::from(data.len());
+		self.memory.resize_offset(start, len)?;
+
+		if !data.is_empty() {
+			let start = as_usize(start).map_err(|_| ExitError::InvalidRange)?;
+			self.memory.set(start, data, None)?;
+		}
+
+		self.return_range = Some(ReturnRange::new(start, len).ok_or(ExitError::InvalidRange)?);
+		Ok(())
 	}
 
 	/// Explicit exit of the machine. Further step will return error.
@@ -101,30 +386,43 @@ impl Machine {
 		self.code.get(position).map(|v| (Opcode(*v), &self.stack))
 	}
 
+	/// Return value range set by `RETURN` or `REVERT`, if any.
+	pub fn return_range(&self) -> Option<&ReturnRange> {
+		self.return_range.as_ref()
+	}
+
+	/// Whether the return value was set by `RETURN` or `REVERT`. This
+	/// distinguishes an empty return value from no return value at all, such as
+	/// after `STOP` or a trap.
+	pub fn had_return(&self) -> bool {
+		self.return_range.is_some()
+	}
+
 	/// Copy and get the return value of the machine, if any.
+	///
+	/// This is valid both after `RETURN` and after `REVERT`, in which case it
+	/// holds the revert data.
 	pub fn return_value(&self) -> Vec<u8> {
-		if self.return_range.start > U256::from(usize::MAX) {
-			let mut ret = Vec::new();
-			ret.resize(
-				(self.return_range.end - self.return_range.start).as_usize(),
-				0,
-			);
-			ret
-		} else if self.return_range.end > U256::from(usize::MAX) {
-			let mut ret = self.memory.get(
-				self.return_range.start.as_usize(),
-				usize::MAX - self.return_range.start.as_usize(),
-			);
-			while ret.len() < (self.return_range.end - self.return_range.start).as_usize() {
-				ret.push(0);
-			}
-			ret
-		} else {
-			self.memory.get(
-				self.return_range.start.as_usize(),
-				(self.return_range.end - self.return_range.start).as_usize(),
-			)
+		self.return_range
+			.as_ref()
+			.map(|range| range.materialize(&self.memory))
+			.unwrap_or_default()
+	}
+
+	/// Push the outputs of an opcode that trapped, so the caller can implement
+	/// it and then keep stepping. The program counter is already past the
+	/// opcode. Values are pushed in order, so the last one ends up on top. If
+	/// they do not all fit, nothing is pushed.
+	pub fn resume_from_trap(&mut self, pushes: &[U256]) -> Result<(), ExitError> {
+		if self.stack.len() + pushes.len() > self.stack.limit() {
+			return Err(ExitError::StackOverflow);
+		}
+
+		for value in pushes {
+			self.stack.push(*value)?;
 		}
+
+		Ok(())
 	}
 
 	/// Loop stepping the machine, until it stops.
@@ -137,6 +435,63 @@ impl Machine {
 		}
 	}
 
+	/// Loop stepping the machine until `pred` matches the next instruction,
+	/// given its position and opcode, or the machine stops.
+	///
+	/// Returns `Ok(position)` when paused at a matching instruction, which has
+	/// not been executed yet. Calling this again with the same predicate pauses
+	/// at the same place; `step` first to move past it.
+	pub fn run_until<F>(&mut self, pred: F) -> Result<usize, Capture<ExitReason, Trap>>
+	where
+		F: Fn(usize, Opcode) -> bool,
+	{
+		loop {
+			if let Ok(position) = self.position {
+				if let Some(opcode) = self.code.get(position).map(|v| Opcode(*v)) {
+					if pred(position, opcode) {
+						return Ok(position);
+					}
+				}
+			}
+
+			self.step()?;
+		}
+	}
+
+	/// Run the rest of the current basic block (see `BasicBlocks`), stepping
+	/// at least once.
+	///
+	/// Returns `Ok(position)` when paused at the start of the next block, which
+	/// has not been executed yet. Traps are returned like in `run`; calling
+	/// this again after handling one finishes the block.
+	pub fn run_block(&mut self) -> Result<usize, Capture<ExitReason, Trap>> {
+		loop {
+			let opcode = self.inspect().map(|(opcode, _)| opcode);
+			self.step()?;
+
+			// A step only succeeds if there was an opcode to run.
+			if let (Some(opcode), Ok(position)) = (opcode, &self.position) {
+				if opcode.is_terminating() || self.valids.is_valid(*position) {
+					return Ok(*position);
+				}
+			}
+		}
+	}
+
+	/// Step the machine at most `n` times, so a debugger can advance it in
+	/// slices. Returns the number of steps taken, and `Some` capture only if
+	/// the machine stopped or trapped within the budget; with `None` it is
+	/// still paused and can be resumed.
+	pub fn step_budget(&mut self, n: u64) -> (u64, Option<Capture<ExitReason, Trap>>) {
+		for taken in 0..n {
+			if let Err(capture) = self.step() {
+				return (taken + 1, Some(capture));
+			}
+		}
+
+		(n, None)
+	}
+
 	#[inline]
 	/// Step the machine, executing one opcode. It then returns.
 	pub fn step(&mut self) -> Result<(), Capture<ExitReason, Trap>> {
@@ -145,29 +500,73 @@ impl Machine {
 			.as_ref()
 			.map_err(|reason| Capture::Exit(reason.clone()))?;
 
-		match self.code.get(position).map(|v| Opcode(*v)) {
-			Some(opcode) => match eval(self, opcode, position) {
-				Control::Continue(p) => {
-					self.position = Ok(position + p);
-					Ok(())
-				}
-				Control::Exit(e) => {
-					self.position = Err(e.clone());
-					Err(Capture::Exit(e))
-				}
-				Control::Jump(p) => {
-					self.position = Ok(p);
-					Ok(())
+		let opcode = match self.code.get(position).map(|v| Opcode(*v)) {
+			Some(opcode) => opcode,
+			None => {
+				let reason = if self.report_out_of_code {
+					ExitSucceed::OutOfCode
+				} else {
+					ExitSucceed::Stopped
+				};
+				return Err(self.fail(reason.into()));
+			}
+		};
+
+		if !opcode.available_in(self.fork) {
+			return Err(self.fail(ExitError::InvalidOpcode.into()));
+		}
+
+		if let Some(gas) = self.gas {
+			match gas.checked_sub(opcode.base_gas()) {
+				Some(gas) => self.gas = Some(gas),
+				None => return Err(self.fail(ExitError::OutOfGas.into())),
+			}
+		}
+
+		if let Some(profile) = &mut self.profile {
+			profile.steps[opcode.as_usize()] += 1;
+		}
+		if let Some(coverage) = &mut self.coverage {
+			coverage[position] = true;
+		}
+
+		let custom = self
+			.custom_opcodes
+			.as_ref()
+			.and_then(|custom| custom[opcode.as_usize()]);
+		let control = match custom {
+			Some(f) => f(self),
+			None => eval(self, opcode, position),
+		};
+
+		match control {
+			Control::Continue(p) => {
+				self.position = Ok(position + p);
+				Ok(())
+			}
+			Control::Exit(e) => Err(self.fail(e)),
+			Control::Jump(p) => {
+				self.position = Ok(p);
+				Ok(())
+			}
+			Control::Trap(opcode) => {
+				if let Some(profile) = &mut self.profile {
+					profile.traps[opcode.as_usize()] += 1;
 				}
-				Control::Trap(opcode) => {
+
+				if self.trap_on_unknown {
 					self.position = Ok(position + 1);
 					Err(Capture::Trap(opcode))
+				} else {
+					Err(self.fail(ExitError::InvalidOpcode.into()))
 				}
-			},
-			None => {
-				self.position = Err(ExitSucceed::Stopped.into());
-				Err(Capture::Exit(ExitSucceed::Stopped.into()))
 			}
 		}
 	}
+
+	/// Stop the machine with `reason`, returning it for `step` to report.
+	fn fail(&mut self, reason: ExitReason) -> Capture<ExitReason, Trap> {
+		self.position = Err(reason.clone());
+		Capture::Exit(reason)
+	}
 }
@@ -1,28 +1,165 @@
-use crate::{ExitError, ExitFatal};
+use crate::{as_usize, ExitError, Shared};
 use alloc::vec::Vec;
+#[cfg(not(feature = "threadsafe"))]
+use core::cell::Cell;
 use core::cmp::min;
 use core::ops::{BitAnd, Not};
+#[cfg(feature = "threadsafe")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 use primitive_types::{H256, U256};
 
+/// Memory budget shared by several memories, such as all frames of a call
+/// tree, capping their total effective length. Like `Shared`, the counter is
+/// atomic only with the `threadsafe` feature.
+#[derive(Debug)]
+pub struct MemoryBudget {
+	limit: usize,
+	#[cfg(not(feature = "threadsafe"))]
+	used: Cell<usize>,
+	#[cfg(feature = "threadsafe")]
+	used: AtomicUsize,
+}
+
+impl MemoryBudget {
+	/// Create a new budget of `limit` bytes.
+	pub fn new(limit: usize) -> Self {
+		Self {
+			limit,
+			used: Default::default(),
+		}
+	}
+
+	/// Total bytes the memories sharing this budget may use.
+	pub fn limit(&self) -> usize {
+		self.limit
+	}
+
+	/// Bytes currently used by the memories sharing this budget.
+	#[cfg(not(feature = "threadsafe"))]
+	pub fn used(&self) -> usize {
+		self.used.get()
+	}
+
+	/// Bytes currently used by the memories sharing this budget.
+	#[cfg(feature = "threadsafe")]
+	pub fn used(&self) -> usize {
+		self.used.load(Ordering::SeqCst)
+	}
+
+	#[cfg(not(feature = "threadsafe"))]
+	fn debit(&self, amount: usize) -> bool {
+		match self.used.get().checked_add(amount) {
+			Some(total) if total <= self.limit => {
+				self.used.set(total);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	#[cfg(feature = "threadsafe")]
+	fn debit(&self, amount: usize) -> bool {
+		self.used
+			.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+				used.checked_add(amount)
+					.filter(|total| *total <= self.limit)
+			})
+			.is_ok()
+	}
+
+	#[cfg(not(feature = "threadsafe"))]
+	fn credit(&self, amount: usize) {
+		self.used.set(self.used.get() - amount);
+	}
+
+	#[cfg(feature = "threadsafe")]
+	fn credit(&self, amount: usize) {
+		self.used.fetch_sub(amount, Ordering::SeqCst);
+	}
+}
+
 /// A sequencial memory. It uses Rust's `Vec` for internal
 /// representation.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Memory {
 	data: Vec<u8>,
 	effective_len: U256,
 	limit: usize,
+	budget: Option<Shared<MemoryBudget>>,
+}
+
+/// A clone has no budget, so it is capped by its limit only: the original's
+/// budget is not charged for the copied bytes. Call `set_budget` on the clone
+/// to charge it.
+impl Clone for Memory {
+	fn clone(&self) -> Self {
+		Self {
+			data: self.data.clone(),
+			effective_len: self.effective_len,
+			limit: self.limit,
+			budget: None,
+		}
+	}
+}
+
+impl Drop for Memory {
+	fn drop(&mut self) {
+		if let Some(budget) = self.budget.take() {
+			budget.credit(self.effective_len.as_usize());
+		}
+	}
 }
 
 impl Memory {
-	/// Create a new memory with the given limit.
+	/// Create a new memory with the given limit. Resizing or writing a range
+	/// that ends past `limit` fails with `ExitError::InvalidRange`. A range may
+	/// end exactly at the limit even if it is not a multiple of 32; the
+	/// effective length, and so `MSIZE`, is then rounded up past the limit,
+	/// but the bytes beyond it can never be read or written.
 	pub fn new(limit: usize) -> Self {
 		Self {
 			data: Vec::new(),
 			effective_len: U256::zero(),
 			limit,
+			budget: None,
 		}
 	}
 
+	/// Create a new memory with the given limit, with backing storage for
+	/// `initial_capacity` bytes reserved up front. The capacity is capped at
+	/// the limit.
+	pub fn with_capacity(limit: usize, initial_capacity: usize) -> Self {
+		Self {
+			data: Vec::with_capacity(min(initial_capacity, limit)),
+			effective_len: U256::zero(),
+			limit,
+			budget: None,
+		}
+	}
+
+	/// Charge growth of the effective length to `budget`, on top of the limit
+	/// of this memory. The current effective length is charged right away and
+	/// returned to the budget when the memory is dropped. Fails with
+	/// `InvalidRange`, keeping the previous budget, if it does not fit.
+	pub fn set_budget(&mut self, budget: Shared<MemoryBudget>) -> Result<(), ExitError> {
+		if self.effective_len > U256::from(usize::MAX)
+			|| !budget.debit(self.effective_len.as_usize())
+		{
+			return Err(ExitError::InvalidRange);
+		}
+
+		if let Some(old) = self.budget.replace(budget) {
+			old.credit(self.effective_len.as_usize());
+		}
+
+		Ok(())
+	}
+
+	/// Budget shared with other memories, if any.
+	pub fn budget(&self) -> Option<&Shared<MemoryBudget>> {
+		self.budget.as_ref()
+	}
+
 	/// Memory limit.
 	pub fn limit(&self) -> usize {
 		self.limit
@@ -33,6 +170,12 @@ impl Memory {
 		self.data.len()
 	}
 
+	/// Number of bytes the memory can hold before its backing storage has to
+	/// be reallocated.
+	pub fn capacity(&self) -> usize {
+		self.data.capacity()
+	}
+
 	/// Get the effective length.
 	pub fn effective_len(&self) -> U256 {
 		self.effective_len
@@ -48,9 +191,9 @@ impl Memory {
 		&self.data
 	}
 
-	/// Resize the memory, making it cover the memory region of `offset..(offset
-	/// + len)`, with 32 bytes as the step. If the length is zero, this function
-	/// does nothing.
+	/// Resize the memory, making it cover the memory region of
+	/// `offset..(offset + len)`, with 32 bytes as the step. If the length is
+	/// zero, this function does nothing.
 	pub fn resize_offset(&mut self, offset: U256, len: U256) -> Result<(), ExitError> {
 		if len == U256::zero() {
 			return Ok(());
@@ -64,15 +207,74 @@ impl Memory {
 	}
 
 	/// Resize the memory, making it cover to `end`, with 32 bytes as the step.
+	/// Fails with `InvalidRange` if `end` is over the limit, see `Memory::new`.
 	pub fn resize_end(&mut self, end: U256) -> Result<(), ExitError> {
+		if end > U256::from(self.limit) {
+			return Err(ExitError::InvalidRange);
+		}
+
 		if end > self.effective_len {
 			let new_end = next_multiple_of_32(end).ok_or(ExitError::InvalidRange)?;
+
+			if let Some(budget) = &self.budget {
+				let growth = new_end - self.effective_len;
+				if growth > U256::from(usize::MAX) || !budget.debit(growth.as_usize()) {
+					return Err(ExitError::InvalidRange);
+				}
+			}
+
 			self.effective_len = new_end;
 		}
 
 		Ok(())
 	}
 
+	/// Grow the underlying buffer to at least `new_len` bytes, zero-filling the
+	/// new bytes. This only reserves backing storage ahead of a known-large
+	/// write; the effective length (and so `MSIZE`) is left untouched. Fails
+	/// with `InvalidRange` if `new_len` is over the memory limit.
+	pub fn expand_to(&mut self, new_len: usize) -> Result<(), ExitError> {
+		if new_len > self.limit {
+			return Err(ExitError::InvalidRange);
+		}
+
+		self.grow(new_len);
+		Ok(())
+	}
+
+	/// Most bytes the buffer may ever need: the limit, or less if the budget
+	/// cannot cover growth up to it.
+	fn max_len(&self) -> usize {
+		match &self.budget {
+			Some(budget) => {
+				let left = budget.limit() - budget.used();
+				let effective_len = as_usize(self.effective_len).unwrap_or(usize::MAX);
+				min(self.limit, effective_len.saturating_add(left))
+			}
+			None => self.limit,
+		}
+	}
+
+	/// Zero-fill the buffer up to `new_len` bytes. When it has to reallocate,
+	/// the capacity is rounded up to a power of two, capped at `max_len`, so
+	/// that many small expansions only reallocate a logarithmic number of
+	/// times. The caller checks `new_len` against the limit.
+	fn grow(&mut self, new_len: usize) {
+		if self.data.len() >= new_len {
+			return;
+		}
+
+		if new_len > self.data.capacity() {
+			let capacity = new_len
+				.checked_next_power_of_two()
+				.map_or(new_len, |c| min(c, self.max_len()))
+				.max(new_len);
+			self.data.reserve_exact(capacity - self.data.len());
+		}
+
+		self.data.resize(new_len, 0);
+	}
+
 	/// Get memory region at given offset.
 	///
 	/// ## Panics
@@ -80,8 +282,7 @@ impl Memory {
 	/// Value of `size` is considered trusted. If they're too large,
 	/// the program can run out of memory, or it can overflow.
 	pub fn get(&self, offset: usize, size: usize) -> Vec<u8> {
-		let mut ret = Vec::new();
-		ret.resize(size, 0);
+		let mut ret = alloc::vec![0; size];
 
 		#[allow(clippy::needless_range_loop)]
 		for index in 0..size {
@@ -114,13 +315,13 @@ impl Memory {
 	}
 
 	/// Set memory region at given offset. The offset and value is considered
-	/// untrusted.
+	/// untrusted. Fails with `InvalidRange` if the region ends past the limit.
 	pub fn set(
 		&mut self,
 		offset: usize,
 		value: &[u8],
 		target_size: Option<usize>,
-	) -> Result<(), ExitFatal> {
+	) -> Result<(), ExitError> {
 		let target_size = target_size.unwrap_or(value.len());
 		if target_size == 0 {
 			return Ok(());
@@ -131,12 +332,10 @@ impl Memory {
 			.map(|pos| pos > self.limit)
 			.unwrap_or(true)
 		{
-			return Err(ExitFatal::NotSupported);
+			return Err(ExitError::InvalidRange);
 		}
 
-		if self.data.len() < offset + target_size {
-			self.data.resize(offset + target_size, 0);
-		}
+		self.grow(offset + target_size);
 
 		if target_size > value.len() {
 			self.data[offset..((value.len()) + offset)].clone_from_slice(value);
@@ -150,14 +349,15 @@ impl Memory {
 		Ok(())
 	}
 
-	/// Copy `data` into the memory, of given `len`.
+	/// Copy `data` into the memory, of given `len`. Fails with `InvalidRange`
+	/// if the region ends past the limit.
 	pub fn copy_large(
 		&mut self,
 		memory_offset: U256,
 		data_offset: U256,
 		len: U256,
 		data: &[u8],
-	) -> Result<(), ExitFatal> {
+	) -> Result<(), ExitError> {
 		// Needed to pass ethereum test defined in
 		// https://github.com/ethereum/tests/commit/17f7e7a6c64bb878c1b6af9dc8371b46c133e46d
 		// (regardless of other inputs, a zero-length copy is defined to be a no-op).
@@ -167,17 +367,8 @@ impl Memory {
 			return Ok(());
 		}
 
-		let memory_offset = if memory_offset > U256::from(usize::MAX) {
-			return Err(ExitFatal::NotSupported);
-		} else {
-			memory_offset.as_usize()
-		};
-
-		let ulen = if len > U256::from(usize::MAX) {
-			return Err(ExitFatal::NotSupported);
-		} else {
-			len.as_usize()
-		};
+		let memory_offset = as_usize(memory_offset).map_err(|_| ExitError::InvalidRange)?;
+		let ulen = as_usize(len).map_err(|_| ExitError::InvalidRange)?;
 
 		let data = if let Some(end) = data_offset.checked_add(len) {
 			if end > U256::from(usize::MAX) {
@@ -209,7 +400,8 @@ fn next_multiple_of_32(x: U256) -> Option<U256> {
 
 #[cfg(test)]
 mod tests {
-	use super::{next_multiple_of_32, U256};
+	use super::{next_multiple_of_32, ExitError, Memory, MemoryBudget, H256, U256};
+	use crate::Shared;
 
 	#[test]
 	fn test_next_multiple_of_32() {
@@ -242,4 +434,232 @@ mod tests {
 			}
 		}
 	}
+
+	#[test]
+	fn test_resize_offset_at_limit() {
+		let limit = 1024;
+		let mut memory = Memory::new(limit);
+		assert_eq!(
+			memory.resize_offset(U256::from(limit - 32), U256::from(33)),
+			Err(ExitError::InvalidRange)
+		);
+		assert_eq!(memory.effective_len(), U256::zero());
+		memory
+			.resize_offset(U256::from(limit - 32), U256::from(32))
+			.unwrap();
+		assert_eq!(memory.effective_len(), U256::from(limit));
+
+		// A limit off the word boundary is checked before rounding: a range
+		// may end exactly at it, and resize and set agree on that edge.
+		let mut memory = Memory::new(100);
+		memory
+			.resize_offset(U256::from(68), U256::from(32))
+			.unwrap();
+		assert_eq!(memory.effective_len(), U256::from(128));
+		memory.set(68, &[0xff; 32], None).unwrap();
+		assert_eq!(
+			memory.resize_offset(U256::from(69), U256::from(32)),
+			Err(ExitError::InvalidRange)
+		);
+		assert_eq!(
+			memory.set(69, &[0xff; 32], None),
+			Err(ExitError::InvalidRange)
+		);
+		assert_eq!(memory.effective_len(), U256::from(128));
+
+		// An empty range never grows, whatever the offset.
+		let mut memory = Memory::new(0);
+		memory.resize_offset(U256::zero(), U256::zero()).unwrap();
+		memory.resize_offset(U256::MAX, U256::zero()).unwrap();
+		assert_eq!(memory.effective_len(), U256::zero());
+	}
+
+	#[test]
+	fn test_memory_zero_fill_on_grow() {
+		let mut memory = Memory::new(usize::MAX);
+
+		memory.resize_offset(U256::zero(), U256::from(32)).unwrap();
+		memory.set(0, &[0xff; 32], None).unwrap();
+
+		// Touching a fresh word grows the memory; the new bytes must read as zero.
+		memory
+			.resize_offset(U256::from(64), U256::from(32))
+			.unwrap();
+		assert_eq!(memory.effective_len(), U256::from(96));
+		assert_eq!(memory.get(64, 32), vec![0; 32]);
+		assert_eq!(memory.get(0, 32), vec![0xff; 32]);
+
+		// Writing past the allocated buffer must zero the gap rather than expose
+		// stale capacity.
+		memory.set(64, &[0xaa; 32], None).unwrap();
+		assert_eq!(memory.get(32, 32), vec![0; 32]);
+		assert_eq!(memory.data()[..32], [0xff; 32]);
+		assert_eq!(memory.data()[64..], [0xaa; 32]);
+
+		// A short value with a larger target size overwrites the tail with zeros, so
+		// reusing a region never leaves the previous contents behind.
+		memory.set(0, &[0x01], Some(96)).unwrap();
+		let mut expected = vec![0; 96];
+		expected[0] = 0x01;
+		assert_eq!(memory.get(0, 96), expected);
+	}
+
+	#[test]
+	fn test_memory_get_zero_pads_past_end() {
+		// Reserved capacity is never read: only initialized bytes count.
+		let mut memory = Memory::with_capacity(usize::MAX, 1024);
+		memory.set(0, &[0xff; 8], None).unwrap();
+		assert_eq!(memory.len(), 8);
+
+		let mut expected = vec![0xff; 8];
+		expected.resize(32, 0);
+		assert_eq!(memory.get(0, 32), expected);
+		assert_eq!(memory.get(4, 8), [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+		assert_eq!(memory.get(8, 16), vec![0; 16]);
+		assert_eq!(memory.get(512, 32), vec![0; 32]);
+
+		// The effective length past the buffer reads as zero too.
+		memory
+			.resize_offset(U256::from(64), U256::from(32))
+			.unwrap();
+		assert_eq!(memory.effective_len(), U256::from(96));
+		assert_eq!(memory.get(64, 32), vec![0; 32]);
+		assert_eq!(memory.get_h256(4), H256::from_slice(&memory.get(4, 32)));
+	}
+
+	#[test]
+	fn test_memory_expand_to() {
+		let mut memory = Memory::new(64);
+
+		memory.set(0, &[0xff; 8], None).unwrap();
+		memory.expand_to(64).unwrap();
+		assert_eq!(memory.len(), 64);
+		assert_eq!(memory.effective_len(), U256::zero());
+		assert_eq!(memory.get(0, 8), vec![0xff; 8]);
+		assert_eq!(memory.get(8, 56), vec![0; 56]);
+
+		// Expanding never shrinks.
+		memory.expand_to(16).unwrap();
+		assert_eq!(memory.len(), 64);
+
+		assert_eq!(memory.expand_to(65), Err(ExitError::InvalidRange));
+		assert_eq!(memory.len(), 64);
+	}
+
+	#[test]
+	fn test_memory_growth_reallocates_rarely() {
+		let mut memory = Memory::new(4096);
+		let mut reallocations = 0;
+		let mut capacity = memory.capacity();
+		for offset in 0..4096 {
+			memory.set(offset, &[1], None).unwrap();
+			if memory.capacity() != capacity {
+				reallocations += 1;
+				capacity = memory.capacity();
+			}
+		}
+		assert_eq!(memory.len(), 4096);
+		assert!(reallocations <= 13);
+
+		let memory = Memory::with_capacity(64, 1024);
+		assert!(memory.capacity() >= 64);
+		assert_eq!(memory.len(), 0);
+	}
+
+	#[test]
+	fn test_memory_capacity_is_capped() {
+		// Rounding 65 bytes up to a power of two would reserve 128.
+		let mut memory = Memory::new(100);
+		memory.set(0, &[1; 65], None).unwrap();
+		assert!(memory.capacity() <= 100);
+
+		// A budget smaller than the limit caps the reserve too.
+		let mut memory = Memory::new(usize::MAX);
+		memory
+			.set_budget(Shared::new(MemoryBudget::new(96)))
+			.unwrap();
+		memory.resize_offset(U256::zero(), U256::from(65)).unwrap();
+		memory.set(0, &[1; 65], None).unwrap();
+		assert!(memory.capacity() >= 65);
+		assert!(memory.capacity() <= 96);
+	}
+
+	#[test]
+	fn test_memory_budget_debit_and_credit() {
+		let budget = MemoryBudget::new(64);
+		assert!(budget.debit(40));
+		assert!(!budget.debit(25));
+		assert_eq!(budget.used(), 40);
+		assert!(budget.debit(24));
+		assert!(!budget.debit(usize::MAX));
+		assert_eq!(budget.used(), 64);
+		budget.credit(64);
+		assert_eq!(budget.used(), 0);
+	}
+
+	#[test]
+	fn test_memory_budget_is_shared() {
+		let budget = Shared::new(MemoryBudget::new(64));
+		let mut first = Memory::new(usize::MAX);
+		let mut second = Memory::new(usize::MAX);
+		first.set_budget(budget.clone()).unwrap();
+		second.set_budget(budget.clone()).unwrap();
+
+		first.resize_end(U256::from(20)).unwrap();
+		assert_eq!(budget.used(), 32);
+		assert_eq!(
+			second.resize_end(U256::from(64)),
+			Err(ExitError::InvalidRange)
+		);
+		assert_eq!(second.effective_len(), U256::zero());
+		second.resize_end(U256::from(32)).unwrap();
+		assert_eq!(budget.used(), 64);
+
+		// Clones do not take part in the budget.
+		drop(first.clone());
+		assert_eq!(budget.used(), 64);
+
+		drop(first);
+		assert_eq!(budget.used(), 32);
+		second.resize_end(U256::from(64)).unwrap();
+		assert_eq!(budget.used(), 64);
+	}
+
+	#[test]
+	fn test_copy_large_bounds() {
+		let mut memory = Memory::new(usize::MAX);
+
+		// A zero-length copy is a no-op, whatever the offsets.
+		assert_eq!(
+			memory.copy_large(U256::MAX, U256::MAX, U256::zero(), &[1, 2, 3]),
+			Ok(())
+		);
+		assert!(memory.is_empty());
+
+		// The destination end overflowing `usize` fails cleanly.
+		assert_eq!(
+			memory.copy_large(U256::from(usize::MAX), U256::zero(), U256::from(2), &[1, 2]),
+			Err(ExitError::InvalidRange)
+		);
+		assert_eq!(
+			memory.copy_large(U256::MAX, U256::zero(), U256::one(), &[1]),
+			Err(ExitError::InvalidRange)
+		);
+		assert!(memory.is_empty());
+
+		// Reading past the source zero-fills.
+		memory.set(0, &[0xff; 8], None).unwrap();
+		memory
+			.copy_large(U256::zero(), U256::from(4), U256::from(8), &[1, 2, 3])
+			.unwrap();
+		assert_eq!(memory.get(0, 8), vec![0; 8]);
+		memory
+			.copy_large(U256::zero(), U256::MAX, U256::from(4), &[1, 2, 3])
+			.unwrap();
+		assert_eq!(memory.get(0, 4), vec![0; 4]);
+		memory
+			.copy_large(U256::zero(), U256::from(1), U256::from(4), &[1, 2, 3])
+			.unwrap();
+		assert_eq!(memory.get(0, 4), vec![2, 3, 0, 0]);
+	}
 }
@@ -1,3 +1,8 @@
+use crate::Hardfork;
+use core::cmp::min;
+use core::fmt;
+use primitive_types::U256;
+
 /// Opcode enum. One-to-one corresponding to an `u8` value.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Opcode(pub u8);
@@ -88,6 +93,14 @@ impl Opcode {
 	pub const MSIZE: Opcode = Opcode(0x59);
 	/// `JUMPDEST`
 	pub const JUMPDEST: Opcode = Opcode(0x5b);
+	/// `PUSH0`, see [EIP-3855](https://eips.ethereum.org/EIPS/eip-3855).
+	pub const PUSH0: Opcode = Opcode(0x5f);
+	/// `RJUMP`, see [EIP-4200](https://eips.ethereum.org/EIPS/eip-4200). Only
+	/// executed with the `eof` feature.
+	pub const RJUMP: Opcode = Opcode(0xe0);
+	/// `RJUMPI`, see [EIP-4200](https://eips.ethereum.org/EIPS/eip-4200). Only
+	/// executed with the `eof` feature.
+	pub const RJUMPI: Opcode = Opcode(0xe1);
 
 	/// `PUSHn`
 	pub const PUSH1: Opcode = Opcode(0x60);
@@ -251,13 +264,383 @@ impl Opcode {
 		}
 	}
 
+	/// Number of immediate bytes following the opcode in code.
+	pub fn immediate_size(&self) -> usize {
+		if let Some(n) = self.is_push() {
+			return n as usize;
+		}
+
+		#[cfg(feature = "eof")]
+		if *self == Opcode::RJUMP || *self == Opcode::RJUMPI {
+			return 2;
+		}
+
+		0
+	}
+
+	/// Read the immediate value of the push opcode at `pc` in `code`. Bytes
+	/// past the end of the code read as zero. Returns `None` if `pc` is out of
+	/// range or not a push opcode.
+	pub fn read_immediate(code: &[u8], pc: usize) -> Option<U256> {
+		let n = Opcode(*code.get(pc)?).is_push()? as usize;
+		let start = pc + 1;
+		let available = min(n, code.len() - start);
+
+		let mut bytes = [0u8; 32];
+		bytes[(32 - n)..(32 - n + available)].copy_from_slice(&code[start..(start + available)]);
+		Some(U256::from_big_endian(&bytes))
+	}
+
+	/// Whether the opcode ends a basic block, because it halts execution or may
+	/// jump.
+	pub fn is_terminating(&self) -> bool {
+		matches!(
+			*self,
+			Opcode::STOP
+				| Opcode::JUMP
+				| Opcode::JUMPI
+				| Opcode::RETURN
+				| Opcode::REVERT
+				| Opcode::INVALID
+				| Opcode::SUICIDE
+		)
+	}
+
+	/// Whether the opcode is defined in `fork`. Bytes that are not opcodes in
+	/// any fork count as available, so that they reach the usual handling for
+	/// unknown opcodes.
+	pub fn available_in(&self, fork: Hardfork) -> bool {
+		let introduced = match *self {
+			Opcode::DELEGATECALL => Hardfork::Homestead,
+			Opcode::REVERT
+			| Opcode::RETURNDATASIZE
+			| Opcode::RETURNDATACOPY
+			| Opcode::STATICCALL => Hardfork::Byzantium,
+			Opcode::SHL | Opcode::SHR | Opcode::SAR | Opcode::CREATE2 | Opcode::EXTCODEHASH => {
+				Hardfork::Constantinople
+			}
+			Opcode::CHAINID | Opcode::SELFBALANCE => Hardfork::Istanbul,
+			Opcode::BASEFEE => Hardfork::London,
+			Opcode::PUSH0 => Hardfork::Shanghai,
+			_ => Hardfork::Frontier,
+		};
+
+		fork >= introduced
+	}
+
+	/// Fixed gas cost of the opcode, by the Yellow Paper fee tiers. Dynamic
+	/// parts such as memory expansion, copied words or `EXP` exponent bytes are
+	/// not included. Opcodes whose cost depends on the hard fork or on state
+	/// access, such as `SLOAD` or `CALL`, and unknown opcodes cost zero here.
+	pub fn base_gas(&self) -> u64 {
+		if self.is_push().is_some() || (Opcode::DUP1.0..=Opcode::SWAP16.0).contains(&self.0) {
+			return 3;
+		}
+
+		match *self {
+			Opcode::JUMPDEST => 1,
+			Opcode::ADDRESS
+			| Opcode::ORIGIN
+			| Opcode::CALLER
+			| Opcode::CALLVALUE
+			| Opcode::CALLDATASIZE
+			| Opcode::RETURNDATASIZE
+			| Opcode::CODESIZE
+			| Opcode::GASPRICE
+			| Opcode::COINBASE
+			| Opcode::TIMESTAMP
+			| Opcode::NUMBER
+			| Opcode::DIFFICULTY
+			| Opcode::GASLIMIT
+			| Opcode::CHAINID
+			| Opcode::BASEFEE
+			| Opcode::PUSH0
+			| Opcode::POP
+			| Opcode::PC
+			| Opcode::MSIZE
+			| Opcode::GAS => 2,
+			Opcode::ADD
+			| Opcode::SUB
+			| Opcode::NOT
+			| Opcode::LT
+			| Opcode::GT
+			| Opcode::SLT
+			| Opcode::SGT
+			| Opcode::EQ
+			| Opcode::ISZERO
+			| Opcode::AND
+			| Opcode::OR
+			| Opcode::XOR
+			| Opcode::BYTE
+			| Opcode::SHL
+			| Opcode::SHR
+			| Opcode::SAR
+			| Opcode::CALLDATALOAD
+			| Opcode::MLOAD
+			| Opcode::MSTORE
+			| Opcode::MSTORE8
+			| Opcode::CALLDATACOPY
+			| Opcode::CODECOPY
+			| Opcode::RETURNDATACOPY => 3,
+			Opcode::MUL
+			| Opcode::DIV
+			| Opcode::SDIV
+			| Opcode::MOD
+			| Opcode::SMOD
+			| Opcode::SIGNEXTEND
+			| Opcode::SELFBALANCE => 5,
+			Opcode::ADDMOD | Opcode::MULMOD | Opcode::JUMP => 8,
+			Opcode::JUMPI | Opcode::EXP => 10,
+			Opcode::BLOCKHASH => 20,
+			Opcode::SHA3 => 30,
+			Opcode::LOG0 => 375,
+			Opcode::LOG1 => 750,
+			Opcode::LOG2 => 1125,
+			Opcode::LOG3 => 1500,
+			Opcode::LOG4 => 1875,
+			Opcode::CREATE | Opcode::CREATE2 => 32000,
+			_ => 0,
+		}
+	}
+
+	/// Mnemonic of the opcode, or `None` if the byte is not a known opcode.
+	pub fn name(&self) -> Option<&'static str> {
+		Some(match *self {
+			Opcode::STOP => "STOP",
+			Opcode::ADD => "ADD",
+			Opcode::MUL => "MUL",
+			Opcode::SUB => "SUB",
+			Opcode::DIV => "DIV",
+			Opcode::SDIV => "SDIV",
+			Opcode::MOD => "MOD",
+			Opcode::SMOD => "SMOD",
+			Opcode::ADDMOD => "ADDMOD",
+			Opcode::MULMOD => "MULMOD",
+			Opcode::EXP => "EXP",
+			Opcode::SIGNEXTEND => "SIGNEXTEND",
+			Opcode::LT => "LT",
+			Opcode::GT => "GT",
+			Opcode::SLT => "SLT",
+			Opcode::SGT => "SGT",
+			Opcode::EQ => "EQ",
+			Opcode::ISZERO => "ISZERO",
+			Opcode::AND => "AND",
+			Opcode::OR => "OR",
+			Opcode::XOR => "XOR",
+			Opcode::NOT => "NOT",
+			Opcode::BYTE => "BYTE",
+			Opcode::CALLDATALOAD => "CALLDATALOAD",
+			Opcode::CALLDATASIZE => "CALLDATASIZE",
+			Opcode::CALLDATACOPY => "CALLDATACOPY",
+			Opcode::CODESIZE => "CODESIZE",
+			Opcode::CODECOPY => "CODECOPY",
+			Opcode::SHL => "SHL",
+			Opcode::SHR => "SHR",
+			Opcode::SAR => "SAR",
+			Opcode::POP => "POP",
+			Opcode::MLOAD => "MLOAD",
+			Opcode::MSTORE => "MSTORE",
+			Opcode::MSTORE8 => "MSTORE8",
+			Opcode::JUMP => "JUMP",
+			Opcode::JUMPI => "JUMPI",
+			Opcode::PC => "PC",
+			Opcode::MSIZE => "MSIZE",
+			Opcode::JUMPDEST => "JUMPDEST",
+			#[cfg(feature = "eof")]
+			Opcode::RJUMP => "RJUMP",
+			#[cfg(feature = "eof")]
+			Opcode::RJUMPI => "RJUMPI",
+			Opcode::PUSH0 => "PUSH0",
+			Opcode::PUSH1 => "PUSH1",
+			Opcode::PUSH2 => "PUSH2",
+			Opcode::PUSH3 => "PUSH3",
+			Opcode::PUSH4 => "PUSH4",
+			Opcode::PUSH5 => "PUSH5",
+			Opcode::PUSH6 => "PUSH6",
+			Opcode::PUSH7 => "PUSH7",
+			Opcode::PUSH8 => "PUSH8",
+			Opcode::PUSH9 => "PUSH9",
+			Opcode::PUSH10 => "PUSH10",
+			Opcode::PUSH11 => "PUSH11",
+			Opcode::PUSH12 => "PUSH12",
+			Opcode::PUSH13 => "PUSH13",
+			Opcode::PUSH14 => "PUSH14",
+			Opcode::PUSH15 => "PUSH15",
+			Opcode::PUSH16 => "PUSH16",
+			Opcode::PUSH17 => "PUSH17",
+			Opcode::PUSH18 => "PUSH18",
+			Opcode::PUSH19 => "PUSH19",
+			Opcode::PUSH20 => "PUSH20",
+			Opcode::PUSH21 => "PUSH21",
+			Opcode::PUSH22 => "PUSH22",
+			Opcode::PUSH23 => "PUSH23",
+			Opcode::PUSH24 => "PUSH24",
+			Opcode::PUSH25 => "PUSH25",
+			Opcode::PUSH26 => "PUSH26",
+			Opcode::PUSH27 => "PUSH27",
+			Opcode::PUSH28 => "PUSH28",
+			Opcode::PUSH29 => "PUSH29",
+			Opcode::PUSH30 => "PUSH30",
+			Opcode::PUSH31 => "PUSH31",
+			Opcode::PUSH32 => "PUSH32",
+			Opcode::DUP1 => "DUP1",
+			Opcode::DUP2 => "DUP2",
+			Opcode::DUP3 => "DUP3",
+			Opcode::DUP4 => "DUP4",
+			Opcode::DUP5 => "DUP5",
+			Opcode::DUP6 => "DUP6",
+			Opcode::DUP7 => "DUP7",
+			Opcode::DUP8 => "DUP8",
+			Opcode::DUP9 => "DUP9",
+			Opcode::DUP10 => "DUP10",
+			Opcode::DUP11 => "DUP11",
+			Opcode::DUP12 => "DUP12",
+			Opcode::DUP13 => "DUP13",
+			Opcode::DUP14 => "DUP14",
+			Opcode::DUP15 => "DUP15",
+			Opcode::DUP16 => "DUP16",
+			Opcode::SWAP1 => "SWAP1",
+			Opcode::SWAP2 => "SWAP2",
+			Opcode::SWAP3 => "SWAP3",
+			Opcode::SWAP4 => "SWAP4",
+			Opcode::SWAP5 => "SWAP5",
+			Opcode::SWAP6 => "SWAP6",
+			Opcode::SWAP7 => "SWAP7",
+			Opcode::SWAP8 => "SWAP8",
+			Opcode::SWAP9 => "SWAP9",
+			Opcode::SWAP10 => "SWAP10",
+			Opcode::SWAP11 => "SWAP11",
+			Opcode::SWAP12 => "SWAP12",
+			Opcode::SWAP13 => "SWAP13",
+			Opcode::SWAP14 => "SWAP14",
+			Opcode::SWAP15 => "SWAP15",
+			Opcode::SWAP16 => "SWAP16",
+			Opcode::RETURN => "RETURN",
+			Opcode::REVERT => "REVERT",
+			Opcode::INVALID => "INVALID",
+			Opcode::SHA3 => "SHA3",
+			Opcode::ADDRESS => "ADDRESS",
+			Opcode::BALANCE => "BALANCE",
+			Opcode::SELFBALANCE => "SELFBALANCE",
+			Opcode::BASEFEE => "BASEFEE",
+			Opcode::ORIGIN => "ORIGIN",
+			Opcode::CALLER => "CALLER",
+			Opcode::CALLVALUE => "CALLVALUE",
+			Opcode::GASPRICE => "GASPRICE",
+			Opcode::EXTCODESIZE => "EXTCODESIZE",
+			Opcode::EXTCODECOPY => "EXTCODECOPY",
+			Opcode::EXTCODEHASH => "EXTCODEHASH",
+			Opcode::RETURNDATASIZE => "RETURNDATASIZE",
+			Opcode::RETURNDATACOPY => "RETURNDATACOPY",
+			Opcode::BLOCKHASH => "BLOCKHASH",
+			Opcode::COINBASE => "COINBASE",
+			Opcode::TIMESTAMP => "TIMESTAMP",
+			Opcode::NUMBER => "NUMBER",
+			Opcode::DIFFICULTY => "DIFFICULTY",
+			Opcode::GASLIMIT => "GASLIMIT",
+			Opcode::SLOAD => "SLOAD",
+			Opcode::SSTORE => "SSTORE",
+			Opcode::GAS => "GAS",
+			Opcode::LOG0 => "LOG0",
+			Opcode::LOG1 => "LOG1",
+			Opcode::LOG2 => "LOG2",
+			Opcode::LOG3 => "LOG3",
+			Opcode::LOG4 => "LOG4",
+			Opcode::CREATE => "CREATE",
+			Opcode::CREATE2 => "CREATE2",
+			Opcode::CALL => "CALL",
+			Opcode::CALLCODE => "CALLCODE",
+			Opcode::DELEGATECALL => "DELEGATECALL",
+			Opcode::STATICCALL => "STATICCALL",
+			Opcode::SUICIDE => "SUICIDE",
+			Opcode::CHAINID => "CHAINID",
+			_ => return None,
+		})
+	}
+
+	/// Opcode with the given mnemonic, as returned by `name`.
+	pub fn from_name(name: &str) -> Option<Opcode> {
+		(0..=u8::MAX)
+			.map(Opcode)
+			.find(|opcode| opcode.name() == Some(name))
+	}
+
 	#[inline]
+	/// Raw byte of the opcode.
 	pub const fn as_u8(&self) -> u8 {
 		self.0
 	}
 
 	#[inline]
+	/// Raw byte of the opcode, as an index.
 	pub const fn as_usize(&self) -> usize {
 		self.0 as usize
 	}
 }
+
+impl fmt::Display for Opcode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.name() {
+			Some(name) => f.write_str(name),
+			None => write!(f, "0x{:02x}", self.0),
+		}
+	}
+}
+
+/// Serializes as the mnemonic, or as `0x`-prefixed hex for unknown bytes.
+/// Deserializes from either form, or from a plain byte.
+#[cfg(feature = "with-serde")]
+impl serde::Serialize for Opcode {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+#[cfg(feature = "with-serde")]
+impl<'de> serde::Deserialize<'de> for Opcode {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct OpcodeVisitor;
+
+		impl<'de> serde::de::Visitor<'de> for OpcodeVisitor {
+			type Value = Opcode;
+
+			fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				f.write_str("an opcode mnemonic, hex byte or integer byte")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Opcode, E> {
+				if let Some(opcode) = Opcode::from_name(value) {
+					return Ok(opcode);
+				}
+
+				value
+					.strip_prefix("0x")
+					.and_then(|hex| u8::from_str_radix(hex, 16).ok())
+					.map(Opcode)
+					.ok_or_else(|| E::invalid_value(serde::de::Unexpected::Str(value), &self))
+			}
+
+			fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Opcode, E> {
+				use core::convert::TryFrom;
+
+				u8::try_from(value)
+					.map(Opcode)
+					.map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(value), &self))
+			}
+		}
+
+		deserializer.deserialize_any(OpcodeVisitor)
+	}
+}
+
+impl From<u8> for Opcode {
+	fn from(value: u8) -> Self {
+		Self(value)
+	}
+}
+
+impl From<Opcode> for u8 {
+	fn from(opcode: Opcode) -> Self {
+		opcode.0
+	}
+}
@@ -0,0 +1,73 @@
+use crate::Memory;
+use alloc::vec::Vec;
+use core::ops::Range;
+use primitive_types::U256;
+
+/// Memory region set by `RETURN` or `REVERT` as the return value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReturnRange(Range<U256>);
+
+impl ReturnRange {
+	/// Create a new return range covering `start..(start + len)`, or `None`
+	/// if the end overflows.
+	pub fn new(start: U256, len: U256) -> Option<Self> {
+		start.checked_add(len).map(|end| Self(start..end))
+	}
+
+	/// Start offset of the range.
+	pub fn start(&self) -> U256 {
+		self.0.start
+	}
+
+	/// Length of the range.
+	pub fn len(&self) -> U256 {
+		self.0.end - self.0.start
+	}
+
+	/// Whether the range is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len().is_zero()
+	}
+
+	/// Copy the range out of `memory`. Bytes past the end of the memory, or
+	/// beyond what `usize` can address, read as zero.
+	pub fn materialize(&self, memory: &Memory) -> Vec<u8> {
+		let Range { start, end } = self.0;
+
+		if start > U256::from(usize::MAX) {
+			alloc::vec![0; self.len().as_usize()]
+		} else if end > U256::from(usize::MAX) {
+			let mut ret = memory.get(start.as_usize(), usize::MAX - start.as_usize());
+			while ret.len() < self.len().as_usize() {
+				ret.push(0);
+			}
+			ret
+		} else {
+			memory.get(start.as_usize(), self.len().as_usize())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Memory, ReturnRange, U256};
+
+	#[test]
+	fn test_materialize_pads_with_zeros() {
+		let mut memory = Memory::new(usize::MAX);
+		memory.set(0, &[0xff; 4], None).unwrap();
+
+		let range = ReturnRange::new(U256::from(2), U256::from(4)).unwrap();
+		assert_eq!(range.len(), U256::from(4));
+		assert_eq!(range.materialize(&memory), vec![0xff, 0xff, 0, 0]);
+
+		let range = ReturnRange::new(U256::from(usize::MAX) + 1, U256::from(4)).unwrap();
+		assert_eq!(range.materialize(&memory), vec![0; 4]);
+
+		let range = ReturnRange::new(U256::from(2), U256::zero()).unwrap();
+		assert!(range.is_empty());
+		assert!(range.materialize(&memory).is_empty());
+
+		assert_eq!(ReturnRange::new(U256::MAX, U256::one()), None);
+	}
+}
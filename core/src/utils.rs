@@ -1,7 +1,89 @@
+use crate::ExitError;
+use alloc::format;
+use alloc::string::String;
 use core::cmp::Ordering;
 use core::ops::{Div, Rem};
 use primitive_types::U256;
 
+/// Convert `v` to `usize`, failing with `ExitError::OutOfOffset` if it does
+/// not fit. This is the conversion opcodes use for memory offsets and lengths.
+pub fn as_usize(v: U256) -> Result<usize, ExitError> {
+	if v > U256::from(usize::MAX) {
+		return Err(ExitError::OutOfOffset);
+	}
+
+	Ok(v.as_usize())
+}
+
+/// Number of significant bytes of `v`, that is, its big-endian length without
+/// leading zero bytes. Zero has no significant bytes. Gasometers use this for
+/// the per-byte `EXP` cost, and it gives the smallest `PUSH` that holds `v`.
+pub fn u256_byte_len(v: U256) -> usize {
+	32 - v.leading_zeros() as usize / 8
+}
+
+/// Gas cost of memory covering `word_count` 32-byte words, `3 * w + w * w /
+/// 512` by the Yellow Paper. Expanding memory costs the difference between
+/// the new and the old word counts. Saturates at `u64::MAX` for counts no
+/// transaction could pay for.
+pub fn memory_gas_cost(word_count: u64) -> u64 {
+	let w = u128::from(word_count);
+	let cost = 3 * w + w * w / 512;
+	if cost > u128::from(u64::MAX) {
+		u64::MAX
+	} else {
+		cost as u64
+	}
+}
+
+/// Selector of Solidity's `Error(string)`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of Solidity's `Panic(uint256)`.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decode the message of revert data, such as `return_value` after `REVERT`.
+/// Recognizes Solidity's `Error(string)`, giving the string, and
+/// `Panic(uint256)`, giving a description of the panic code. Returns `None`
+/// for any other or malformed data.
+pub fn decode_revert_reason(data: &[u8]) -> Option<String> {
+	if data.len() < 4 {
+		return None;
+	}
+	let (selector, args) = data.split_at(4);
+
+	let word = |offset: usize| -> Option<U256> {
+		let end = offset.checked_add(32)?;
+		args.get(offset..end).map(U256::from_big_endian)
+	};
+
+	if selector == ERROR_SELECTOR {
+		let offset = as_usize(word(0)?).ok()?;
+		let len = as_usize(word(offset)?).ok()?;
+		let start = offset.checked_add(32)?;
+		let bytes = args.get(start..start.checked_add(len)?)?;
+		String::from_utf8(bytes.to_vec()).ok()
+	} else if selector == PANIC_SELECTOR {
+		let code = word(0)?;
+		let reason = match code.low_u64() {
+			_ if code > U256::from(u64::MAX) => "unknown panic code",
+			0x00 => "generic panic",
+			0x01 => "assertion failed",
+			0x11 => "arithmetic underflow or overflow",
+			0x12 => "division or modulo by zero",
+			0x21 => "invalid enum value",
+			0x22 => "invalid storage byte array encoding",
+			0x31 => "pop from empty array",
+			0x32 => "array index out of bounds",
+			0x41 => "memory allocation too large",
+			0x51 => "call to uninitialized function",
+			_ => "unknown panic code",
+		};
+		Some(format!("panic: {} (0x{:x})", reason, code))
+	} else {
+		None
+	}
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Sign {
 	Plus,
@@ -131,11 +213,15 @@ impl Rem for I256 {
 
 #[cfg(test)]
 mod tests {
-	use crate::utils::{Sign, I256};
+	use crate::utils::{
+		as_usize, decode_revert_reason, memory_gas_cost, u256_byte_len, Sign, I256,
+	};
+	use crate::ExitError;
 	use primitive_types::U256;
 	use std::num::Wrapping;
 
 	#[test]
+	#[allow(clippy::identity_op)]
 	fn div_i256() {
 		// Sanity checks based on i8. Notice that we need to use `Wrapping` here because
 		// Rust will prevent the overflow by default whereas the EVM does not.
@@ -165,4 +251,65 @@ mod tests {
 		assert_eq!(one_hundred / minus_one, neg_one_hundred);
 		assert_eq!(one_hundred / two, fifty);
 	}
+
+	#[test]
+	fn test_as_usize() {
+		assert_eq!(as_usize(U256::zero()), Ok(0));
+		assert_eq!(as_usize(U256::from(usize::MAX)), Ok(usize::MAX));
+		assert_eq!(
+			as_usize(U256::from(usize::MAX) + 1),
+			Err(ExitError::OutOfOffset)
+		);
+	}
+
+	#[test]
+	fn test_decode_revert_reason() {
+		// Error("Not enough Ether provided.")
+		let error = hex::decode(
+			"08c379a0\
+			 0000000000000000000000000000000000000000000000000000000000000020\
+			 000000000000000000000000000000000000000000000000000000000000001a\
+			 4e6f7420656e6f7567682045746865722070726f76696465642e000000000000",
+		)
+		.unwrap();
+		assert_eq!(
+			decode_revert_reason(&error),
+			Some("Not enough Ether provided.".into())
+		);
+		assert_eq!(decode_revert_reason(&error[..error.len() - 32]), None);
+
+		// Panic(0x11)
+		let panic = hex::decode(
+			"4e487b71\
+			 0000000000000000000000000000000000000000000000000000000000000011",
+		)
+		.unwrap();
+		assert_eq!(
+			decode_revert_reason(&panic),
+			Some("panic: arithmetic underflow or overflow (0x11)".into())
+		);
+
+		assert_eq!(decode_revert_reason(&[]), None);
+		assert_eq!(decode_revert_reason(&[0xde, 0xad, 0xbe, 0xef]), None);
+	}
+
+	#[test]
+	fn test_u256_byte_len() {
+		assert_eq!(u256_byte_len(U256::zero()), 0);
+		assert_eq!(u256_byte_len(U256::one()), 1);
+		assert_eq!(u256_byte_len(U256::from(0xff)), 1);
+		assert_eq!(u256_byte_len(U256::from(0x100)), 2);
+		assert_eq!(u256_byte_len(U256::MAX), 32);
+	}
+
+	#[test]
+	fn test_memory_gas_cost() {
+		assert_eq!(memory_gas_cost(0), 0);
+		assert_eq!(memory_gas_cost(1), 3);
+		assert_eq!(memory_gas_cost(32), 98);
+		// At 1024 words the quadratic term adds 2048 to the linear 3072.
+		assert_eq!(memory_gas_cost(1024), 5120);
+		assert_eq!(memory_gas_cost(u64::from(u32::MAX)), 36028809887088637);
+		assert_eq!(memory_gas_cost(u64::MAX), u64::MAX);
+	}
 }
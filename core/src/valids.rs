@@ -3,35 +3,46 @@ use alloc::vec::Vec;
 
 /// Mapping of valid jump destination from code.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Valids(Vec<bool>);
+pub struct Valids {
+	jumpdests: Vec<bool>,
+	#[cfg(feature = "eof")]
+	instructions: Vec<bool>,
+}
 
 impl Valids {
 	/// Create a new valid mapping from given code bytes.
 	pub fn new(code: &[u8]) -> Self {
-		let mut valids: Vec<bool> = Vec::with_capacity(code.len());
-		valids.resize(code.len(), false);
+		let mut jumpdests: Vec<bool> = Vec::with_capacity(code.len());
+		jumpdests.resize(code.len(), false);
+		#[cfg(feature = "eof")]
+		let mut instructions = jumpdests.clone();
 
 		let mut i = 0;
 		while i < code.len() {
+			#[cfg(feature = "eof")]
+			{
+				instructions[i] = true;
+			}
+
 			let opcode = Opcode(code[i]);
 			if opcode == Opcode::JUMPDEST {
-				valids[i] = true;
-				i += 1;
-			} else if let Some(v) = opcode.is_push() {
-				i += v as usize + 1;
-			} else {
-				i += 1;
+				jumpdests[i] = true;
 			}
+			i += opcode.immediate_size() + 1;
 		}
 
-		Valids(valids)
+		Valids {
+			jumpdests,
+			#[cfg(feature = "eof")]
+			instructions,
+		}
 	}
 
 	/// Get the length of the valid mapping. This is the same as the
 	/// code bytes.
 	#[inline]
 	pub fn len(&self) -> usize {
-		self.0.len()
+		self.jumpdests.len()
 	}
 
 	/// Returns true if the valids list is empty
@@ -43,14 +54,21 @@ impl Valids {
 	/// Returns `true` if the position is a valid jump destination. If
 	/// not, returns `false`.
 	pub fn is_valid(&self, position: usize) -> bool {
-		if position >= self.0.len() {
+		if position >= self.jumpdests.len() {
 			return false;
 		}
 
-		if !self.0[position] {
+		if !self.jumpdests[position] {
 			return false;
 		}
 
 		true
 	}
+
+	/// Returns `true` if an instruction starts at the position, rather than
+	/// immediate data.
+	#[cfg(feature = "eof")]
+	pub fn is_instruction(&self, position: usize) -> bool {
+		self.instructions.get(position).cloned().unwrap_or(false)
+	}
 }
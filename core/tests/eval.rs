@@ -0,0 +1,806 @@
+use evm_core::{
+	register_external, Capture, Control, ExitError, ExitFatal, ExitReason, ExitRevert, ExitSucceed,
+	Hardfork, Machine, Opcode, Shared,
+};
+use primitive_types::{H256, U256};
+
+fn machine(code: &str) -> Machine {
+	let code = hex::decode(code).unwrap();
+	Machine::new(Shared::new(code), Shared::new(Vec::new()), 1024, 10000)
+}
+
+#[test]
+fn jump_to_zero_requires_jumpdest() {
+	// PUSH1 0 JUMP
+	let mut vm = machine("600056");
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidJump.into()));
+
+	// JUMPDEST PUSH1 0 JUMP
+	let mut vm = machine("5b600056");
+	for _ in 0..3 {
+		assert_eq!(vm.step(), Ok(()));
+	}
+	assert_eq!(vm.position(), &Ok(0));
+}
+
+#[test]
+fn revert_data_is_returned() {
+	// PUSH4 0xdeadbeef PUSH1 0 MSTORE PUSH1 4 PUSH1 28 REVERT
+	let mut vm = machine("63deadbeef6000526004601cfd");
+	assert_eq!(vm.run(), Capture::Exit(ExitRevert::Reverted.into()));
+	assert_eq!(vm.return_value(), vec![0xde, 0xad, 0xbe, 0xef]);
+	assert!(vm.had_return());
+}
+
+#[test]
+fn had_return_distinguishes_empty_return() {
+	// STOP
+	let mut vm = machine("00");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert!(vm.return_value().is_empty());
+	assert!(!vm.had_return());
+
+	// PUSH1 0 PUSH1 0 RETURN
+	let mut vm = machine("60006000f3");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Returned.into()));
+	assert!(vm.return_value().is_empty());
+	assert!(vm.had_return());
+}
+
+fn msize_after(code: &str) -> U256 {
+	let mut vm = machine(code);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	vm.stack().peek(0).unwrap()
+}
+
+#[test]
+fn msize_is_word_rounded() {
+	// MSIZE
+	assert_eq!(msize_after("59"), U256::zero());
+	// PUSH1 1 PUSH1 0 MSTORE8 MSIZE
+	assert_eq!(msize_after("600160005359"), U256::from(32));
+	// PUSH1 33 MLOAD MSIZE
+	assert_eq!(msize_after("60215159"), U256::from(96));
+}
+
+#[test]
+fn explicit_stop_differs_from_end_of_code() {
+	// STOP
+	let mut vm = machine("00");
+	vm.set_report_out_of_code(true);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+
+	// PUSH1 0
+	let mut vm = machine("6000");
+	vm.set_report_out_of_code(true);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::OutOfCode.into()));
+
+	let mut vm = machine("");
+	vm.set_report_out_of_code(true);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::OutOfCode.into()));
+
+	// Without opting in, both are reported as a stop.
+	let mut vm = machine("6000");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+}
+
+/// Run `opcode` on `args`, with `args[0]` on top of the stack, and return the
+/// result.
+fn apply(opcode: &str, args: &[U256]) -> U256 {
+	let mut code = String::new();
+	for arg in args.iter().rev() {
+		let mut bytes = [0u8; 32];
+		arg.to_big_endian(&mut bytes);
+		// PUSH32 arg
+		code += "7f";
+		code += &hex::encode(bytes);
+	}
+	code += opcode;
+
+	let mut vm = machine(&code);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	vm.stack().peek(0).unwrap()
+}
+
+#[test]
+fn slt_sgt_around_sign_bit() {
+	let slt = |a, b| apply("12", &[a, b]);
+	let sgt = |a, b| apply("13", &[a, b]);
+
+	let zero = U256::zero();
+	let minus_one = U256::MAX;
+	let min = U256::one() << 255;
+	let max = U256::MAX >> 1;
+
+	assert_eq!(slt(minus_one, zero), U256::one());
+	assert_eq!(slt(zero, minus_one), U256::zero());
+	assert_eq!(sgt(zero, minus_one), U256::one());
+	assert_eq!(slt(min, zero), U256::one());
+	assert_eq!(slt(min, max), U256::one());
+	assert_eq!(sgt(min, max), U256::zero());
+	assert_eq!(sgt(max, min), U256::one());
+
+	for v in [zero, minus_one, min, max] {
+		assert_eq!(slt(v, v), U256::zero());
+		assert_eq!(sgt(v, v), U256::zero());
+	}
+}
+
+#[test]
+fn run_until_pauses_before_breakpoint() {
+	// PUSH1 1 PUSH1 2 ADD PUSH1 3 ADD
+	let mut vm = machine("6001600201600301");
+
+	assert_eq!(vm.run_until(|_, opcode| opcode == Opcode::ADD), Ok(4));
+	assert_eq!(vm.stack().len(), 2);
+	// Still paused at the same instruction until it is stepped past.
+	assert_eq!(vm.run_until(|_, opcode| opcode == Opcode::ADD), Ok(4));
+	assert_eq!(vm.step(), Ok(()));
+
+	assert_eq!(vm.run_until(|pc, _| pc == 7), Ok(7));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(3)));
+
+	assert_eq!(
+		vm.run_until(|_, _| false),
+		Err(Capture::Exit(ExitSucceed::Stopped.into()))
+	);
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(6)));
+}
+
+#[test]
+fn addmod_mulmod_use_wide_intermediates() {
+	let addmod = |a, b, m| apply("08", &[a, b, m]);
+	let mulmod = |a, b, m| apply("09", &[a, b, m]);
+
+	assert_eq!(addmod(U256::MAX, U256::MAX, U256::zero()), U256::zero());
+	assert_eq!(mulmod(U256::MAX, U256::MAX, U256::zero()), U256::zero());
+	// MAX + 1 = 2^256 would wrap to zero.
+	assert_eq!(addmod(U256::MAX, U256::one(), U256::MAX), U256::one());
+	// 2^256 - 1 = 1 (mod 7), so MAX * MAX = 1 (mod 7).
+	assert_eq!(mulmod(U256::MAX, U256::MAX, U256::from(7)), U256::one());
+	// 2^128 * 2^128 = 2^256 = 1 (mod 3), but would wrap to zero.
+	let half = U256::one() << 128;
+	assert_eq!(mulmod(half, half, U256::from(3)), U256::one());
+}
+
+#[test]
+fn empty_code_exits_immediately() {
+	let mut vm = machine("");
+	assert!(vm.inspect().is_none());
+	// There is no explicit STOP, so this is the end-of-code exit.
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.step(), Err(Capture::Exit(ExitSucceed::Stopped.into())));
+	assert!(vm.return_value().is_empty());
+	assert!(!vm.had_return());
+}
+
+#[test]
+fn push_immediate_is_right_padded() {
+	let code = hex::decode("61abcd62ab").unwrap();
+	assert_eq!(Opcode::read_immediate(&code, 0), Some(U256::from(0xabcd)));
+	assert_eq!(Opcode::read_immediate(&code, 3), Some(U256::from(0xab0000)));
+	assert_eq!(Opcode::read_immediate(&code, 1), None);
+	assert_eq!(Opcode::read_immediate(&code, 5), None);
+
+	// PUSH2 0xab (truncated)
+	let mut vm = machine("61ab");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(0xab00)));
+
+	// PUSH3 0xabcd (truncated)
+	let mut vm = machine("62abcd");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(0xabcd00)));
+}
+
+#[test]
+fn unknown_opcode_exits_when_not_trapping() {
+	// 0x0c (undefined)
+	let mut vm = machine("0c");
+	assert_eq!(vm.run(), Capture::Trap(Opcode(0x0c)));
+	assert_eq!(vm.position(), &Ok(1));
+
+	let mut vm = machine("0c");
+	vm.set_trap_on_unknown(false);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidOpcode.into()));
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidOpcode.into()));
+}
+
+#[test]
+fn return_range_overflow_is_out_of_offset() {
+	// PUSH1 1 PUSH32 MAX RETURN
+	let mut vm = machine(&format!("60017f{}f3", "ff".repeat(32)));
+	assert_eq!(vm.run(), Capture::Exit(ExitError::OutOfOffset.into()));
+	assert!(!vm.had_return());
+
+	// PUSH1 1 PUSH32 MAX REVERT
+	let mut vm = machine(&format!("60017f{}fd", "ff".repeat(32)));
+	assert_eq!(vm.run(), Capture::Exit(ExitError::OutOfOffset.into()));
+	assert!(!vm.had_return());
+}
+
+#[test]
+fn set_data_and_reset_rerun_code() {
+	// PUSH1 0 CALLDATALOAD PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+	let mut vm = machine("60003560005260206000f3");
+	vm.set_data(Shared::new(vec![0xaa; 32]));
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Returned.into()));
+	assert_eq!(vm.return_value(), vec![0xaa; 32]);
+
+	vm.reset().unwrap();
+	assert_eq!(vm.position(), &Ok(0));
+	assert!(vm.stack().is_empty());
+	assert!(vm.memory().is_empty());
+	assert!(!vm.had_return());
+
+	vm.set_data(Shared::new(vec![0xbb; 32]));
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Returned.into()));
+	assert_eq!(vm.return_value(), vec![0xbb; 32]);
+}
+
+#[test]
+fn grown_memory_reads_as_zero() {
+	// PUSH1 0x20 PUSH1 0 MSTORE PUSH1 64 MLOAD
+	let mut vm = machine("6020600052604051");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().data(), &vec![U256::zero()]);
+	assert_eq!(vm.memory().effective_len(), U256::from(96));
+}
+
+#[cfg(feature = "threadsafe")]
+#[test]
+fn machine_is_send_and_sync() {
+	fn assert_send_sync<T: Send + Sync>() {}
+	assert_send_sync::<Machine>();
+}
+
+#[test]
+fn set_return_keeps_memory() {
+	// PUSH1 0xaa PUSH1 0 MSTORE8
+	let mut vm = machine("60aa600053");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+
+	vm.set_return(&[1, 2, 3]).unwrap();
+	vm.exit(ExitSucceed::Returned.into());
+	assert_eq!(vm.return_value(), vec![1, 2, 3]);
+	assert!(vm.had_return());
+	assert_eq!(vm.memory().get(0, 1), vec![0xaa]);
+
+	let mut vm = machine("");
+	vm.set_return(&[]).unwrap();
+	assert!(vm.had_return());
+	assert!(vm.return_value().is_empty());
+
+	let mut vm = Machine::empty(1024, 16);
+	assert_eq!(vm.set_return(&[0; 17]), Err(ExitError::InvalidRange));
+	assert!(!vm.had_return());
+}
+
+#[test]
+fn jumpi_condition_and_destination() {
+	// JUMPI is taken for any non-zero condition.
+	for condition in &["00", "01"] {
+		// PUSH1 condition PUSH1 6 JUMPI STOP JUMPDEST
+		let mut vm = machine(&format!("60{}600657005b", condition));
+		for _ in 0..3 {
+			assert_eq!(vm.step(), Ok(()));
+		}
+		let expected = if *condition == "00" { 5 } else { 6 };
+		assert_eq!(vm.position(), &Ok(expected));
+	}
+	// PUSH32 MAX PUSH1 0x25 JUMPI STOP JUMPDEST
+	let mut vm = machine(&format!("7f{}602557005b", "ff".repeat(32)));
+	for _ in 0..3 {
+		assert_eq!(vm.step(), Ok(()));
+	}
+	assert_eq!(vm.position(), &Ok(0x25));
+
+	// The destination is only validated when the jump is taken.
+	// PUSH1 1 PUSH1 0xff JUMPI
+	let mut vm = machine("600160ff57");
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidJump.into()));
+	// PUSH1 0 PUSH1 0xff JUMPI
+	let mut vm = machine("600060ff57");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn push_reads_big_endian_and_advances() {
+	let word = (1..=32).collect::<Vec<u8>>();
+	let cases = [
+		("60ff".to_string(), U256::from(0xff), 2),
+		("610102".to_string(), U256::from(0x0102), 3),
+		("62010203".to_string(), U256::from(0x010203), 4),
+		(
+			format!("7f{}", hex::encode(&word)),
+			U256::from_big_endian(&word),
+			33,
+		),
+	];
+
+	for (code, value, advance) in cases.iter() {
+		let mut vm = machine(code);
+		assert_eq!(vm.step(), Ok(()));
+		assert_eq!(vm.position(), &Ok(*advance));
+		assert_eq!(vm.stack().peek(0), Ok(*value));
+	}
+}
+
+#[test]
+fn resume_from_trap_pushes_outputs() {
+	// 0x0c PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+	let mut vm = machine("0c60005260206000f3");
+	assert_eq!(vm.run(), Capture::Trap(Opcode(0x0c)));
+
+	vm.resume_from_trap(&[U256::from(42)]).unwrap();
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Returned.into()));
+	assert_eq!(U256::from_big_endian(&vm.return_value()), U256::from(42));
+
+	let mut vm = Machine::new(Shared::new(vec![0x0c]), Shared::new(Vec::new()), 1, 10000);
+	assert_eq!(vm.run(), Capture::Trap(Opcode(0x0c)));
+	assert_eq!(
+		vm.resume_from_trap(&[U256::one(), U256::one()]),
+		Err(ExitError::StackOverflow)
+	);
+	assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn capture_into_result() {
+	// STOP
+	let capture = machine("00").run();
+	assert!(capture.is_exit() && !capture.is_trap());
+	assert_eq!(capture.into_result(), Ok(ExitSucceed::Stopped.into()));
+
+	// 0x0c (undefined)
+	let capture = machine("0c").run();
+	assert!(capture.is_trap() && !capture.is_exit());
+	assert_eq!(capture.into_result(), Err(Opcode(0x0c)));
+}
+
+#[cfg(feature = "eof")]
+#[test]
+fn rjump_uses_relative_offset() {
+	// RJUMP +1 INVALID PUSH1 7
+	let mut vm = machine("e00001fe6007");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(7)));
+
+	// PUSH1 1 RJUMPI +1 INVALID PUSH1 0 RJUMPI -6
+	let mut vm = machine("6001e10001fe6000e1fffa");
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert!(vm.stack().is_empty());
+
+	// RJUMP +1 into the immediate of PUSH1
+	let mut vm = machine("e000016000");
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidJump.into()));
+
+	// RJUMP -4, before the start of the code
+	let mut vm = machine("e0fffc");
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidJump.into()));
+}
+
+#[test]
+fn code_keccak_hashes_code() {
+	let mut vm = machine("");
+	let hash = H256::from_slice(
+		&hex::decode("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470").unwrap(),
+	);
+	assert_eq!(vm.code_keccak(), hash);
+	assert_eq!(vm.code_keccak(), hash);
+
+	// The cached hash survives running and resetting the machine.
+	let mut vm = machine("600100");
+	let hash = vm.code_keccak();
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	vm.reset().unwrap();
+	assert_eq!(vm.code_keccak(), hash);
+	assert_ne!(hash, machine("").code_keccak());
+}
+
+/// Code pushing `1..=depth`, so the top of the stack is `depth`, followed by
+/// `opcode`.
+fn with_depth(depth: u8, opcode: Opcode) -> Machine {
+	let mut code = String::new();
+	for i in 1..=depth {
+		code.push_str(&format!("60{:02x}", i));
+	}
+	code.push_str(&format!("{:02x}", opcode.as_u8()));
+	machine(&code)
+}
+
+#[test]
+fn swap_needs_one_more_item_than_depth() {
+	let mut vm = with_depth(1, Opcode::SWAP1);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::StackUnderflow.into()));
+
+	let mut vm = with_depth(16, Opcode::SWAP16);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::StackUnderflow.into()));
+
+	let mut vm = with_depth(17, Opcode::SWAP16);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().len(), 17);
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(1)));
+	assert_eq!(vm.stack().peek(16), Ok(U256::from(17)));
+	for i in 1..16 {
+		assert_eq!(vm.stack().peek(i), Ok(U256::from(17 - i)));
+	}
+}
+
+#[test]
+fn dup_needs_depth_items() {
+	let mut vm = with_depth(0, Opcode::DUP1);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::StackUnderflow.into()));
+
+	let mut vm = with_depth(15, Opcode::DUP16);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::StackUnderflow.into()));
+
+	let mut vm = with_depth(16, Opcode::DUP16);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().len(), 17);
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(1)));
+	assert_eq!(vm.stack().peek(16), Ok(U256::from(1)));
+
+	let mut vm = with_depth(3, Opcode::DUP2);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(2)));
+}
+
+#[test]
+fn from_reader_loads_code() {
+	// PUSH1 1 PUSH1 2 ADD
+	let code = hex::decode("6001600201").unwrap();
+	let mut vm = Machine::from_reader(&code[..], Shared::new(Vec::new()), 1024, 10000).unwrap();
+	assert_eq!(vm.code(), &code[..]);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(3)));
+}
+
+#[test]
+fn opcodes_available_from_their_fork() {
+	assert!(Opcode::ADD.available_in(Hardfork::Frontier));
+	assert!(!Opcode::DELEGATECALL.available_in(Hardfork::Frontier));
+	assert!(Opcode::DELEGATECALL.available_in(Hardfork::Homestead));
+	assert!(!Opcode::SHL.available_in(Hardfork::Byzantium));
+	assert!(Opcode::SHL.available_in(Hardfork::Constantinople));
+	assert!(!Opcode::CHAINID.available_in(Hardfork::Petersburg));
+	assert!(!Opcode::BASEFEE.available_in(Hardfork::Berlin));
+	assert!(Opcode::BASEFEE.available_in(Hardfork::London));
+	assert!(!Opcode::PUSH0.available_in(Hardfork::London));
+	assert!(Opcode::PUSH0.available_in(Hardfork::Shanghai));
+	assert_eq!(Hardfork::latest(), Hardfork::Shanghai);
+}
+
+#[test]
+fn with_fork_rejects_later_opcodes() {
+	// PUSH0
+	let mut vm = machine("5f").with_fork(Hardfork::London);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidOpcode.into()));
+	assert!(vm.stack().data().is_empty());
+
+	let mut vm = machine("5f").with_fork(Hardfork::Shanghai);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().data(), &vec![U256::zero()]);
+
+	// PUSH1 1 PUSH1 4 SHL
+	let mut vm = machine("600160041b").with_fork(Hardfork::Byzantium);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidOpcode.into()));
+	assert_eq!(vm.stack().len(), 2);
+
+	let mut vm = machine("600160041b").with_fork(Hardfork::Constantinople);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(16)));
+
+	// CHAINID is left to the runtime once available.
+	let mut vm = machine("46").with_fork(Hardfork::Petersburg);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::InvalidOpcode.into()));
+	let mut vm = machine("46");
+	assert_eq!(vm.run(), Capture::Trap(Opcode::CHAINID));
+}
+
+#[test]
+fn set_gas_meters_base_costs() {
+	// PUSH1 1 PUSH1 2 ADD: 3 + 3 + 3 gas.
+	let mut vm = machine("6001600201");
+	assert_eq!(vm.gas_left(), None);
+	vm.set_gas(9);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.gas_left(), Some(0));
+
+	let mut vm = machine("6001600201");
+	vm.set_gas(8);
+	assert_eq!(vm.run(), Capture::Exit(ExitError::OutOfGas.into()));
+	assert_eq!(vm.stack().len(), 2);
+	assert_eq!(vm.gas_left(), Some(2));
+}
+
+#[test]
+fn zero_length_copies_ignore_offsets() {
+	let max = "7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+	for opcode in ["37", "39"] {
+		// PUSH1 0 PUSH32 MAX PUSH32 MAX (CALLDATACOPY | CODECOPY) MSIZE
+		let code = format!("6000{}{}{}59", max, max, opcode);
+		assert_eq!(msize_after(&code), U256::zero());
+	}
+}
+
+#[test]
+fn jumpdests_skip_push_data() {
+	// JUMPDEST PUSH1 0x5b JUMPDEST PUSH2 0x5b5b STOP JUMPDEST
+	let vm = machine("5b605b5b615b5b005b");
+	assert_eq!(vm.jumpdests(), vec![0, 3, 8]);
+	assert!(machine("").jumpdests().is_empty());
+}
+
+#[test]
+fn opcode_displays_mnemonic() {
+	assert_eq!(Opcode::ADD.to_string(), "ADD");
+	assert_eq!(Opcode::PUSH32.to_string(), "PUSH32");
+	assert_eq!(Opcode(0x0c).to_string(), "0x0c");
+	assert_eq!(Opcode::from_name("SWAP16"), Some(Opcode::SWAP16));
+	assert_eq!(Opcode::from_name("0x0c"), None);
+}
+
+#[test]
+fn profile_counts_steps_and_traps() {
+	// PUSH1 1 PUSH1 2 ADD CALLER
+	let mut vm = machine("600160020133");
+	vm.enable_profile();
+	assert_eq!(vm.run(), Capture::Trap(Opcode::CALLER));
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+
+	let steps = vm.step_profile();
+	assert_eq!(steps[Opcode::PUSH1.as_usize()], 2);
+	assert_eq!(steps[Opcode::ADD.as_usize()], 1);
+	assert_eq!(steps[Opcode::CALLER.as_usize()], 1);
+	assert_eq!(steps.iter().sum::<u64>(), 4);
+
+	let traps = vm.trap_profile();
+	assert_eq!(traps[Opcode::CALLER.as_usize()], 1);
+	assert_eq!(traps.iter().sum::<u64>(), 1);
+
+	assert_eq!(machine("00").trap_profile(), [0; 256]);
+}
+
+#[test]
+fn pc_and_is_running_follow_position() {
+	// PUSH1 1 STOP
+	let mut vm = machine("600100");
+	assert!(vm.is_running());
+	assert_eq!(vm.pc(), Some(0));
+	assert_eq!(vm.step(), Ok(()));
+	assert_eq!(vm.pc(), Some(2));
+	assert_eq!(vm.step(), Err(Capture::Exit(ExitSucceed::Stopped.into())));
+	assert!(!vm.is_running());
+	assert_eq!(vm.pc(), None);
+}
+
+#[test]
+fn run_block_pauses_between_blocks() {
+	// PUSH1 1 PUSH1 2 ADD PUSH1 8 JUMP | JUMPDEST PUSH1 3 | JUMPDEST STOP
+	let mut vm = machine("60016002016008565b60035b00");
+	assert_eq!(vm.run_block(), Ok(8));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(3)));
+	assert_eq!(vm.run_block(), Ok(11));
+	assert_eq!(vm.stack().len(), 2);
+	assert_eq!(
+		vm.run_block(),
+		Err(Capture::Exit(ExitSucceed::Stopped.into()))
+	);
+}
+
+#[test]
+fn with_stack_seeds_operands() {
+	let code = Shared::new(vec![Opcode::SUB.as_u8()]);
+	let stack = vec![U256::from(2), U256::from(5)];
+	let mut vm =
+		Machine::with_stack(code.clone(), Shared::new(Vec::new()), stack, 1024, 0).unwrap();
+	assert_eq!(vm.step(), Ok(()));
+	assert_eq!(vm.stack().data(), &vec![U256::from(3)]);
+
+	assert!(matches!(
+		Machine::with_stack(code, Shared::new(Vec::new()), vec![U256::one(); 3], 2, 0),
+		Err(ExitError::StackOverflow)
+	));
+}
+
+/// Opcodes that need a handler, and so are left to the runtime.
+const EXTERNAL: &[Opcode] = &[
+	Opcode::SHA3,
+	Opcode::ADDRESS,
+	Opcode::BALANCE,
+	Opcode::ORIGIN,
+	Opcode::CALLER,
+	Opcode::CALLVALUE,
+	Opcode::GASPRICE,
+	Opcode::EXTCODESIZE,
+	Opcode::EXTCODECOPY,
+	Opcode::RETURNDATASIZE,
+	Opcode::RETURNDATACOPY,
+	Opcode::EXTCODEHASH,
+	Opcode::BLOCKHASH,
+	Opcode::COINBASE,
+	Opcode::TIMESTAMP,
+	Opcode::NUMBER,
+	Opcode::DIFFICULTY,
+	Opcode::GASLIMIT,
+	Opcode::CHAINID,
+	Opcode::SELFBALANCE,
+	Opcode::BASEFEE,
+	Opcode::SLOAD,
+	Opcode::SSTORE,
+	Opcode::GAS,
+	Opcode::LOG0,
+	Opcode::LOG1,
+	Opcode::LOG2,
+	Opcode::LOG3,
+	Opcode::LOG4,
+	Opcode::CREATE,
+	Opcode::CALL,
+	Opcode::CALLCODE,
+	Opcode::DELEGATECALL,
+	Opcode::CREATE2,
+	Opcode::STATICCALL,
+	Opcode::SUICIDE,
+];
+
+#[test]
+fn defined_opcodes_are_registered() {
+	for byte in 0..=255u8 {
+		let opcode = Opcode(byte);
+		if opcode.name().is_none() {
+			continue;
+		}
+
+		// Every opcode evaluated in core fails on the empty stack or exits on
+		// its own; only a missing table entry traps.
+		let mut vm = Machine::new(
+			Shared::new(vec![byte]),
+			Shared::new(Vec::new()),
+			1024,
+			10000,
+		);
+		let trapped = matches!(vm.step(), Err(Capture::Trap(_)));
+		assert_eq!(trapped, EXTERNAL.contains(&opcode), "{}", opcode);
+	}
+}
+
+#[test]
+fn step_budget_stays_resumable() {
+	// PUSH1 1 PUSH1 2 ADD STOP
+	let mut vm = machine("600160020100");
+	assert_eq!(vm.step_budget(2), (2, None));
+	assert_eq!(vm.pc(), Some(4));
+	assert_eq!(vm.step_budget(0), (0, None));
+	assert_eq!(
+		vm.step_budget(10),
+		(2, Some(Capture::Exit(ExitSucceed::Stopped.into())))
+	);
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(3)));
+}
+
+#[test]
+fn empty_machine_stops_at_once() {
+	let mut vm = Machine::empty(1024, 10000);
+	assert!(vm.is_running());
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert!(vm.stack().data().is_empty());
+}
+
+fn eval_double(vm: &mut Machine) -> Control {
+	match vm.stack_mut().pop() {
+		Ok(value) => match vm.stack_mut().push(value * 2) {
+			Ok(()) => Control::Continue(1),
+			Err(e) => Control::Exit(e.into()),
+		},
+		Err(e) => Control::Exit(e.into()),
+	}
+}
+
+#[test]
+fn registered_opcode_runs_before_trap() {
+	// PUSH1 21 0xb0 STOP
+	let mut vm = machine("6015b000");
+	vm.register_opcode(Opcode(0xb0), eval_double);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(42)));
+
+	// Unregistered bytes still trap.
+	let mut vm = machine("6015b000");
+	vm.register_opcode(Opcode(0xb1), eval_double);
+	assert_eq!(vm.run(), Capture::Trap(Opcode(0xb0)));
+}
+
+fn skip_one(_vm: &mut Machine) -> Control {
+	Control::Continue(2)
+}
+
+fn jump_to_five(_vm: &mut Machine) -> Control {
+	Control::Jump(5)
+}
+
+fn trap_self(_vm: &mut Machine) -> Control {
+	Control::Trap(Opcode(0xb2))
+}
+
+fn revert(_vm: &mut Machine) -> Control {
+	Control::Exit(ExitRevert::Reverted.into())
+}
+
+#[test]
+fn step_handles_every_control() {
+	// 0xb0 INVALID 0xb1 INVALID INVALID 0xb2 0xb3
+	let mut vm = machine("b0feb1fefeb2b3");
+	vm.register_opcode(Opcode(0xb0), skip_one);
+	vm.register_opcode(Opcode(0xb1), jump_to_five);
+	vm.register_opcode(Opcode(0xb2), trap_self);
+	vm.register_opcode(Opcode(0xb3), revert);
+
+	assert_eq!(vm.step(), Ok(()));
+	assert_eq!(vm.position(), &Ok(2));
+	assert_eq!(vm.step(), Ok(()));
+	assert_eq!(vm.position(), &Ok(5));
+	assert_eq!(vm.step(), Err(Capture::Trap(Opcode(0xb2))));
+	assert_eq!(vm.position(), &Ok(6));
+	assert_eq!(vm.step(), Err(Capture::Exit(ExitRevert::Reverted.into())));
+	assert_eq!(vm.position(), &Err(ExitRevert::Reverted.into()));
+}
+
+fn broken_invariant(_vm: &mut Machine) -> Control {
+	Control::Exit(ExitFatal::Other("broken invariant".into()).into())
+}
+
+#[test]
+fn fatal_exit_carries_message() {
+	// 0xb0
+	let mut vm = machine("b0");
+	vm.register_opcode(Opcode(0xb0), broken_invariant);
+	let reason = ExitReason::Fatal(ExitFatal::Other("broken invariant".into()));
+	assert_eq!(vm.run(), Capture::Exit(reason.clone()));
+	assert_eq!(vm.run(), Capture::Exit(reason));
+}
+
+#[test]
+fn external_table_runs_registered_opcode() {
+	let mut table = [None; 256];
+	register_external(&mut table, Opcode(0xb0), eval_double);
+
+	// PUSH1 21 0xb0 STOP
+	let mut vm = machine("6015b000");
+	vm.set_external_table(table);
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(vm.stack().peek(0), Ok(U256::from(42)));
+}
+
+#[test]
+fn coverage_marks_executed_pcs() {
+	// PUSH1 5 JUMP PUSH1 0 JUMPDEST STOP
+	let mut vm = machine("60055660005b00");
+	assert!(vm.covered_pcs().is_empty());
+	vm.enable_coverage();
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+	assert_eq!(
+		vm.covered_pcs(),
+		&[true, false, true, false, false, true, true]
+	);
+}
+
+#[test]
+fn top_opcodes_break_ties_by_byte() {
+	// CALLER PUSH1 1 PUSH1 2 ADD
+	let mut vm = machine("336001600201");
+	vm.enable_profile();
+	assert_eq!(vm.run(), Capture::Trap(Opcode::CALLER));
+	assert_eq!(vm.run(), Capture::Exit(ExitSucceed::Stopped.into()));
+
+	assert_eq!(
+		vm.top_opcodes(3),
+		vec![(Opcode::PUSH1, 2), (Opcode::ADD, 1), (Opcode::CALLER, 1)]
+	);
+	assert_eq!(
+		vm.top_opcodes(2),
+		vec![(Opcode::PUSH1, 2), (Opcode::ADD, 1)]
+	);
+	assert!(machine("00").top_opcodes(3).is_empty());
+}
@@ -1,10 +1,9 @@
-use evm_core::Machine;
-use std::rc::Rc;
+use evm_core::{Machine, Shared};
 
 fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-	return haystack
+	haystack
 		.windows(needle.len())
-		.position(|window| window == needle);
+		.position(|window| window == needle)
 }
 
 fn split_at_delim(sequence: &[u8], delim: &[u8]) -> (Vec<u8>, Vec<u8>) {
@@ -27,7 +26,12 @@ fn handle_data(sequence: &[u8]) {
 	let (code, data) = split_at_delim(sequence, vec![0xde, 0xad, 0xbe, 0xef].as_slice());
 	let stack_limit = 1024;
 	let memory_limit = 10000;
-	let mut vm = Machine::new(Rc::new(code), Rc::new(data), stack_limit, memory_limit);
+	let mut vm = Machine::new(
+		Shared::new(code),
+		Shared::new(data),
+		stack_limit,
+		memory_limit,
+	);
 	let res = vm.run();
 	#[cfg(not(fuzzing))]
 	{
@@ -59,7 +63,7 @@ fn main() {
 				.unwrap()
 				.map(|x| x.unwrap().path().to_str().unwrap().to_string())
 				.collect::<Vec<String>>(),
-			false => (&args[1..]).to_vec(),
+			false => args[1..].to_vec(),
 		};
 		for argument in all_files {
 			println!("Now doing file {:?}", argument);
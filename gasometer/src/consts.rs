@@ -10,7 +10,6 @@ pub const G_CREATE: u64 = 32000;
 pub const G_CALLVALUE: u64 = 9000;
 pub const G_NEWACCOUNT: u64 = 25000;
 pub const G_EXP: u64 = 10;
-pub const G_MEMORY: u64 = 3;
 pub const G_LOG: u64 = 375;
 pub const G_LOGDATA: u64 = 8;
 pub const G_LOGTOPIC: u64 = 375;
@@ -1,6 +1,7 @@
 use crate::consts::*;
+use crate::utils::word_count;
 use crate::Config;
-use evm_core::ExitError;
+use evm_core::{u256_byte_len, ExitError};
 use primitive_types::{H256, U256};
 
 pub fn call_extra_check(gas: U256, after_gas: u64, config: &Config) -> Result<(), ExitError> {
@@ -60,15 +61,8 @@ pub fn sstore_refund(original: H256, current: H256, new: H256, config: &Config)
 
 pub fn create2_cost(len: U256) -> Result<u64, ExitError> {
 	let base = U256::from(G_CREATE);
-	// ceil(len / 32.0)
-	let sha_addup_base = len / U256::from(32)
-		+ if len % U256::from(32) == U256::zero() {
-			U256::zero()
-		} else {
-			U256::one()
-		};
 	let sha_addup = U256::from(G_SHA3WORD)
-		.checked_mul(sha_addup_base)
+		.checked_mul(word_count(len))
 		.ok_or(ExitError::OutOfGas)?;
 	let gas = base.checked_add(sha_addup).ok_or(ExitError::OutOfGas)?;
 
@@ -86,7 +80,7 @@ pub fn exp_cost(power: U256, config: &Config) -> Result<u64, ExitError> {
 		let gas = U256::from(G_EXP)
 			.checked_add(
 				U256::from(config.gas_expbyte)
-					.checked_mul(U256::from(crate::utils::log2floor(power) / 8 + 1))
+					.checked_mul(U256::from(u256_byte_len(power)))
 					.ok_or(ExitError::OutOfGas)?,
 			)
 			.ok_or(ExitError::OutOfGas)?;
@@ -100,17 +94,10 @@ pub fn exp_cost(power: U256, config: &Config) -> Result<u64, ExitError> {
 }
 
 pub fn verylowcopy_cost(len: U256) -> Result<u64, ExitError> {
-	let wordd = len / U256::from(32);
-	let wordr = len % U256::from(32);
-
 	let gas = U256::from(G_VERYLOW)
 		.checked_add(
 			U256::from(G_COPY)
-				.checked_mul(if wordr == U256::zero() {
-					wordd
-				} else {
-					wordd + U256::one()
-				})
+				.checked_mul(word_count(len))
 				.ok_or(ExitError::OutOfGas)?,
 		)
 		.ok_or(ExitError::OutOfGas)?;
@@ -123,16 +110,10 @@ pub fn verylowcopy_cost(len: U256) -> Result<u64, ExitError> {
 }
 
 pub fn extcodecopy_cost(len: U256, is_cold: bool, config: &Config) -> Result<u64, ExitError> {
-	let wordd = len / U256::from(32);
-	let wordr = len % U256::from(32);
 	let gas = U256::from(address_access_cost(is_cold, config.gas_ext_code, config))
 		.checked_add(
 			U256::from(G_COPY)
-				.checked_mul(if wordr == U256::zero() {
-					wordd
-				} else {
-					wordd + U256::one()
-				})
+				.checked_mul(word_count(len))
 				.ok_or(ExitError::OutOfGas)?,
 		)
 		.ok_or(ExitError::OutOfGas)?;
@@ -163,17 +144,10 @@ pub fn log_cost(n: u8, len: U256) -> Result<u64, ExitError> {
 }
 
 pub fn sha3_cost(len: U256) -> Result<u64, ExitError> {
-	let wordd = len / U256::from(32);
-	let wordr = len % U256::from(32);
-
 	let gas = U256::from(G_SHA3)
 		.checked_add(
 			U256::from(G_SHA3WORD)
-				.checked_mul(if wordr == U256::zero() {
-					wordd
-				} else {
-					wordd + U256::one()
-				})
+				.checked_mul(word_count(len))
 				.ok_or(ExitError::OutOfGas)?,
 		)
 		.ok_or(ExitError::OutOfGas)?;
@@ -323,3 +297,48 @@ fn new_cost(
 		0
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		exp_cost, extcodecopy_cost, log_cost, sha3_cost, verylowcopy_cost, Config, ExitError, U256,
+	};
+
+	#[test]
+	fn test_sha3_cost_per_word() {
+		assert_eq!(sha3_cost(U256::zero()), Ok(30));
+		assert_eq!(sha3_cost(U256::one()), Ok(36));
+		assert_eq!(sha3_cost(U256::from(32)), Ok(36));
+		assert_eq!(sha3_cost(U256::from(33)), Ok(42));
+		assert_eq!(sha3_cost(U256::MAX), Err(ExitError::OutOfGas));
+	}
+
+	#[test]
+	fn test_exp_cost_per_exponent_byte() {
+		let config = Config::london();
+		assert_eq!(exp_cost(U256::zero(), &config), Ok(10));
+		assert_eq!(exp_cost(U256::from(0xff), &config), Ok(60));
+		assert_eq!(exp_cost(U256::from(0x100), &config), Ok(110));
+		assert_eq!(exp_cost(U256::MAX, &config), Ok(1610));
+	}
+
+	#[test]
+	fn test_log_cost_per_byte_and_topic() {
+		assert_eq!(log_cost(0, U256::zero()), Ok(375));
+		assert_eq!(log_cost(0, U256::from(10)), Ok(455));
+		assert_eq!(log_cost(4, U256::from(10)), Ok(1955));
+		assert_eq!(log_cost(0, U256::MAX), Err(ExitError::OutOfGas));
+	}
+
+	#[test]
+	fn test_copy_cost_per_word() {
+		assert_eq!(verylowcopy_cost(U256::zero()), Ok(3));
+		assert_eq!(verylowcopy_cost(U256::from(32)), Ok(6));
+		assert_eq!(verylowcopy_cost(U256::from(33)), Ok(9));
+
+		// EXTCODECOPY adds the EIP-2929 account access cost instead.
+		let config = Config::london();
+		assert_eq!(extcodecopy_cost(U256::from(33), true, &config), Ok(2606));
+		assert_eq!(extcodecopy_cost(U256::from(33), false, &config), Ok(106));
+	}
+}
@@ -27,6 +27,8 @@ mod costs;
 mod memory;
 mod utils;
 
+pub use crate::utils::word_count;
+
 use alloc::vec::Vec;
 use core::cmp::max;
 use evm_core::{ExitError, Opcode, Stack};
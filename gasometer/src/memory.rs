@@ -1,11 +1,8 @@
-use crate::consts::*;
-use evm_core::ExitError;
+use evm_core::{memory_gas_cost, ExitError};
 
 pub fn memory_gas(a: usize) -> Result<u64, ExitError> {
-	let a = a as u64;
-	G_MEMORY
-		.checked_mul(a)
-		.ok_or(ExitError::OutOfGas)?
-		.checked_add(a.checked_mul(a).ok_or(ExitError::OutOfGas)? / 512)
-		.ok_or(ExitError::OutOfGas)
+	match memory_gas_cost(a as u64) {
+		u64::MAX => Err(ExitError::OutOfGas),
+		cost => Ok(cost),
+	}
 }
@@ -1,20 +1,26 @@
 use primitive_types::U256;
 
-pub fn log2floor(value: U256) -> u64 {
-	assert!(value != U256::zero());
-	let mut l: u64 = 256;
-	for i in 0..4 {
-		let i = 3 - i;
-		if value.0[i] == 0u64 {
-			l -= 64;
-		} else {
-			l -= value.0[i].leading_zeros() as u64;
-			if l == 0 {
-				return l;
-			} else {
-				return l - 1;
-			}
-		}
+/// Number of 32-byte words needed to cover `len` bytes, rounded up. This is the
+/// unit that per-word charges of copy opcodes, `SHA3` and `CREATE2` are based on.
+pub fn word_count(len: U256) -> U256 {
+	let words = len / U256::from(32);
+	if len % U256::from(32) == U256::zero() {
+		words
+	} else {
+		words + U256::one()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{word_count, U256};
+
+	#[test]
+	fn test_word_count() {
+		assert_eq!(word_count(U256::zero()), U256::zero());
+		assert_eq!(word_count(U256::one()), U256::one());
+		assert_eq!(word_count(U256::from(32)), U256::one());
+		assert_eq!(word_count(U256::from(33)), U256::from(2));
+		assert_eq!(word_count(U256::MAX), (U256::MAX >> 5) + U256::one());
 	}
-	l
 }
@@ -1,4 +1,7 @@
+use crate::Handler;
+use alloc::vec::Vec;
 use primitive_types::{H160, H256, U256};
+use sha3::{Digest, Keccak256};
 
 /// Create scheme.
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
@@ -21,6 +24,38 @@ pub enum CreateScheme {
 	Fixed(H160),
 }
 
+impl CreateScheme {
+	/// Address of the contract created with this scheme. `Legacy` addresses
+	/// are derived from the caller's nonce, fetched from `handler`, so they
+	/// panic with handlers that keep the default `Handler::nonce`.
+	pub fn address<H: Handler + ?Sized>(&self, handler: &H) -> H160 {
+		match *self {
+			CreateScheme::Legacy { caller } => legacy_address(caller, handler.nonce(caller)),
+			CreateScheme::Create2 {
+				caller,
+				code_hash,
+				salt,
+			} => {
+				let mut hasher = Keccak256::new();
+				hasher.input([0xff]);
+				hasher.input(&caller[..]);
+				hasher.input(&salt[..]);
+				hasher.input(&code_hash[..]);
+				H256::from_slice(hasher.result().as_slice()).into()
+			}
+			CreateScheme::Fixed(address) => address,
+		}
+	}
+}
+
+/// Address `CREATE` derives from the caller and its nonce.
+fn legacy_address(caller: H160, nonce: U256) -> H160 {
+	let mut stream = rlp::RlpStream::new_list(2);
+	stream.append(&caller);
+	stream.append(&nonce);
+	H256::from_slice(Keccak256::digest(&stream.out()).as_slice()).into()
+}
+
 /// Call scheme.
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum CallScheme {
@@ -34,6 +69,25 @@ pub enum CallScheme {
 	StaticCall,
 }
 
+/// Operands of a call opcode, as read from the stack before it runs.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct CallArgs {
+	/// Gas to forward.
+	pub gas: U256,
+	/// Address called.
+	pub target: H160,
+	/// Value sent, zero for schemes that take none.
+	pub value: U256,
+	/// Offset of the input in memory.
+	pub in_offset: U256,
+	/// Length of the input.
+	pub in_len: U256,
+	/// Offset of the output in memory.
+	pub out_offset: U256,
+	/// Length of the output.
+	pub out_len: U256,
+}
+
 /// Context of the runtime.
 #[derive(Clone, Debug)]
 pub struct Context {
@@ -44,3 +98,29 @@ pub struct Context {
 	/// Apparent value of the EVM.
 	pub apparent_value: U256,
 }
+
+/// Access list of a transaction, as in
+/// [EIP-2930](https://eips.ethereum.org/EIPS/eip-2930): addresses, each with
+/// the storage keys to warm.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AccessList(pub Vec<(H160, Vec<H256>)>);
+
+impl AccessList {
+	/// Addresses in the list.
+	pub fn addresses(&self) -> impl Iterator<Item = H160> + '_ {
+		self.0.iter().map(|(address, _)| *address)
+	}
+
+	/// Storage keys in the list, with their address.
+	pub fn storage_keys(&self) -> impl Iterator<Item = (H160, H256)> + '_ {
+		self.0
+			.iter()
+			.flat_map(|(address, keys)| keys.iter().map(move |key| (*address, *key)))
+	}
+}
+
+impl From<Vec<(H160, Vec<H256>)>> for AccessList {
+	fn from(list: Vec<(H160, Vec<H256>)>) -> Self {
+		Self(list)
+	}
+}
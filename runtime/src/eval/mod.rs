@@ -2,7 +2,7 @@
 mod macros;
 mod system;
 
-use crate::{CallScheme, ExitReason, Handler, Opcode, Runtime};
+use crate::{CallScheme, ExitError, ExitReason, Handler, Opcode, Runtime};
 
 pub enum Control<H: Handler> {
 	Continue,
@@ -55,7 +55,8 @@ pub fn eval<H: Handler>(state: &mut Runtime, opcode: Opcode, handler: &mut H) ->
 		Opcode::DELEGATECALL => system::call(state, CallScheme::DelegateCall, handler),
 		Opcode::STATICCALL => system::call(state, CallScheme::StaticCall, handler),
 		Opcode::CHAINID => system::chainid(state, handler),
-		Opcode::BASEFEE => system::base_fee(state, handler),
+		Opcode::BASEFEE if handler.supports_base_fee() => system::base_fee(state, handler),
+		Opcode::BASEFEE => Control::Exit(ExitError::InvalidOpcode.into()),
 		_ => handle_other(state, opcode, handler),
 	}
 }
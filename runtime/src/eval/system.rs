@@ -1,10 +1,12 @@
 use super::Control;
 use crate::{
-	CallScheme, Capture, Context, CreateScheme, ExitError, ExitFatal, ExitReason, ExitSucceed,
-	Handler, Runtime, Transfer,
+	CallScheme, Capture, Context, CreateScheme, ExitError, ExitReason, ExitSucceed, Handler,
+	Runtime, Transfer,
 };
 use alloc::vec::Vec;
 use core::cmp::min;
+#[cfg(feature = "debug-handler")]
+use primitive_types::H160;
 use primitive_types::{H256, U256};
 use sha3::{Digest, Keccak256};
 
@@ -28,7 +30,15 @@ pub fn sha3<H: Handler>(runtime: &mut Runtime) -> Control<H> {
 }
 
 pub fn chainid<H: Handler>(runtime: &mut Runtime, handler: &H) -> Control<H> {
-	push_u256!(runtime, handler.chain_id());
+	let chain_id = match runtime.chain_id {
+		Some(chain_id) => chain_id,
+		None => {
+			let chain_id = handler.chain_id();
+			runtime.chain_id = Some(chain_id);
+			chain_id
+		}
+	};
+	push_u256!(runtime, chain_id);
 
 	Control::Continue
 }
@@ -91,8 +101,22 @@ pub fn base_fee<H: Handler>(runtime: &mut Runtime, handler: &H) -> Control<H> {
 	Control::Continue
 }
 
+/// Panic if the handler's `code_size` disagrees with the length of its
+/// `code`, since `EXTCODESIZE` and `EXTCODECOPY` rely on one each.
+#[cfg(feature = "debug-handler")]
+fn check_code_size<H: Handler>(handler: &H, address: H160) {
+	assert_eq!(
+		handler.code_size(address),
+		U256::from(handler.code(address).len()),
+		"handler code_size disagrees with code for {:?}",
+		address,
+	);
+}
+
 pub fn extcodesize<H: Handler>(runtime: &mut Runtime, handler: &H) -> Control<H> {
 	pop_h256!(runtime, address);
+	#[cfg(feature = "debug-handler")]
+	check_code_size(handler, address.into());
 	push_u256!(runtime, handler.code_size(address.into()));
 
 	Control::Continue
@@ -108,6 +132,8 @@ pub fn extcodehash<H: Handler>(runtime: &mut Runtime, handler: &H) -> Control<H>
 pub fn extcodecopy<H: Handler>(runtime: &mut Runtime, handler: &H) -> Control<H> {
 	pop_h256!(runtime, address);
 	pop_u256!(runtime, memory_offset, code_offset, len);
+	#[cfg(feature = "debug-handler")]
+	check_code_size(handler, address.into());
 
 	try_or_fail!(runtime
 		.machine
@@ -226,6 +252,8 @@ pub fn gas<H: Handler>(runtime: &mut Runtime, handler: &H) -> Control<H> {
 	Control::Continue
 }
 
+/// `LOGn`. Topics are passed to the handler in stack order: the first topic
+/// popped after the data range is `topics[0]`.
 pub fn log<H: Handler>(runtime: &mut Runtime, n: u8, handler: &mut H) -> Control<H> {
 	pop_u256!(runtime, offset, len);
 
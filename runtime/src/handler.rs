@@ -1,4 +1,6 @@
-use crate::{Capture, Context, CreateScheme, ExitError, ExitReason, Machine, Opcode, Stack};
+use crate::{
+	AccessList, Capture, Context, CreateScheme, ExitError, ExitReason, Machine, Opcode, Stack,
+};
 use alloc::vec::Vec;
 use primitive_types::{H160, H256, U256};
 
@@ -29,6 +31,13 @@ pub trait Handler {
 	fn balance(&self, address: H160) -> U256;
 	/// Get code size of address.
 	fn code_size(&self, address: H160) -> U256;
+	/// Get nonce of address, which `CREATE` derives new addresses from. The
+	/// default panics rather than guess a nonce, as a wrong one silently gives
+	/// wrong addresses; handlers that use `CreateScheme::address` for `CREATE`
+	/// must override it.
+	fn nonce(&self, _address: H160) -> U256 {
+		unimplemented!("Handler::nonce is needed to derive CREATE addresses")
+	}
 	/// Get code hash of address.
 	fn code_hash(&self, address: H160) -> H256;
 	/// Get code of address.
@@ -36,9 +45,20 @@ pub trait Handler {
 	/// Get storage value of address at index.
 	fn storage(&self, address: H160, index: H256) -> H256;
 	/// Get original storage value of address at index.
-	fn original_storage(&self, address: H160, index: H256) -> H256;
+	///
+	/// This is the value as of the start of the transaction, snapshotted before
+	/// any `SSTORE` in it, and is what EIP-2200/EIP-3529 `SSTORE` gas is
+	/// computed against. The default returns the current value, which is only
+	/// correct for handlers that do not meter `SSTORE` that way.
+	fn original_storage(&self, address: H160, index: H256) -> H256 {
+		self.storage(address, index)
+	}
 
 	/// Get the gas left value.
+	///
+	/// When queried by `GAS`, this must already have the cost of the `GAS`
+	/// opcode itself deducted. Gasometers charge it in `pre_validate`, which
+	/// runs before the opcode is evaluated.
 	fn gas_left(&self) -> U256;
 	/// Get the gas price value.
 	fn gas_price(&self) -> U256;
@@ -46,6 +66,15 @@ pub trait Handler {
 	fn origin(&self) -> H160;
 	/// Get environmental block hash.
 	fn block_hash(&self, number: U256) -> H256;
+	/// Get environmental block hashes for `count` blocks starting at `from`.
+	/// The opcode never calls this; it lets embedders backed by a database
+	/// fetch a range in one go. Numbers past `U256::MAX` are left out.
+	fn block_hash_range(&self, from: U256, count: usize) -> Vec<H256> {
+		(0..count)
+			.filter_map(|i| from.checked_add(U256::from(i)))
+			.map(|number| self.block_hash(number))
+			.collect()
+	}
 	/// Get environmental block number.
 	fn block_number(&self) -> U256;
 	/// Get environmental coinbase.
@@ -58,6 +87,12 @@ pub trait Handler {
 	fn block_gas_limit(&self) -> U256;
 	/// Environmental block base fee.
 	fn block_base_fee_per_gas(&self) -> U256;
+	/// Whether `BASEFEE` is defined. When false, it exits with
+	/// `ExitError::InvalidOpcode`, as on chains before
+	/// [EIP-3198](https://eips.ethereum.org/EIPS/eip-3198).
+	fn supports_base_fee(&self) -> bool {
+		true
+	}
 	/// Get environmental chain ID.
 	fn chain_id(&self) -> U256;
 
@@ -65,6 +100,11 @@ pub trait Handler {
 	fn exists(&self, address: H160) -> bool;
 	/// Check whether an address has already been deleted.
 	fn deleted(&self, address: H160) -> bool;
+	/// Whether the current frame is static, because it was entered through
+	/// `STATICCALL` or is nested anywhere below one.
+	fn is_static(&self) -> bool {
+		false
+	}
 	/// Checks if the address or (address, index) pair has been previously accessed
 	/// (or set in `accessed_addresses` / `accessed_storage_keys` via an access list
 	/// transaction).
@@ -72,11 +112,43 @@ pub trait Handler {
 	/// * https://eips.ethereum.org/EIPS/eip-2929
 	/// * https://eips.ethereum.org/EIPS/eip-2930
 	fn is_cold(&self, address: H160, index: Option<H256>) -> bool;
+	/// Mark an address as accessed. The runtime never calls this while
+	/// executing: warming call targets and other accessed addresses is left to
+	/// `pre_validate`, which charges the cold or warm cost and can warm the
+	/// address in the same place, as `StackExecutor` does. The address of a
+	/// frame should be warmed when the handler enters the frame.
+	fn warm_address(&mut self, _address: H160) {}
+	/// Mark the entries of a transaction's access list as accessed, before
+	/// execution begins. The default warms the addresses through
+	/// `warm_address`; handlers that track warm storage keys should override
+	/// this to warm those too.
+	fn prewarm(&mut self, list: &AccessList) {
+		for address in list.addresses() {
+			self.warm_address(address);
+		}
+	}
 
 	/// Set storage value of address at index.
 	fn set_storage(&mut self, address: H160, index: H256, value: H256) -> Result<(), ExitError>;
 	/// Create a log owned by address with given topics and data.
+	///
+	/// `LOG` gas, including the per-byte cost, is due before this is called:
+	/// `pre_validate` sees the data length as the second stack item, which is
+	/// what `data.len()` ends up as.
 	fn log(&mut self, address: H160, topics: Vec<H256>, data: Vec<u8>) -> Result<(), ExitError>;
+	/// Set code of address, finalizing the result of a create operation.
+	/// Implementations should reject code longer than `max_code_size` with
+	/// `ExitError::CreateContractLimit`. The default fails every create with
+	/// `ExitError::Other`, so handlers that finalize creates through this
+	/// method must override it.
+	fn set_code(&mut self, _address: H160, _code: Vec<u8>) -> Result<(), ExitError> {
+		Err(ExitError::Other("set_code is not supported".into()))
+	}
+	/// Maximum size of deployed code, if any. See
+	/// [EIP-170](https://eips.ethereum.org/EIPS/eip-170).
+	fn max_code_size(&self) -> Option<usize> {
+		None
+	}
 	/// Mark an address to be deleted, with funds transferred to target.
 	fn mark_delete(&mut self, address: H160, target: H160) -> Result<(), ExitError>;
 	/// Invoke a create operation.
@@ -115,6 +187,12 @@ pub trait Handler {
 		stack: &Stack,
 	) -> Result<(), ExitError>;
 	/// Handle other unknown external opcodes.
+	///
+	/// This is called for every opcode that neither the core machine nor the
+	/// runtime implements, and is the extension point for custom opcodes. When
+	/// it is called, the program counter has already been moved past the
+	/// opcode. The implementation pops its inputs from and pushes its outputs
+	/// onto the machine stack; returning an error exits the machine with it.
 	fn other(&mut self, _opcode: Opcode, _stack: &mut Machine) -> Result<(), ExitError> {
 		Err(ExitError::OutOfGas)
 	}
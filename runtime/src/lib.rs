@@ -29,23 +29,30 @@ mod interrupt;
 
 pub use evm_core::*;
 
-pub use crate::context::{CallScheme, Context, CreateScheme};
+pub use crate::context::{AccessList, CallArgs, CallScheme, Context, CreateScheme};
 pub use crate::handler::{Handler, Transfer};
 pub use crate::interrupt::{Resolve, ResolveCall, ResolveCreate};
 
-use alloc::rc::Rc;
 use alloc::vec::Vec;
+use primitive_types::U256;
 
 macro_rules! step {
 	( $self:expr, $handler:expr, $return:tt $($err:path)?; $($ok:path)? ) => ({
+		#[cfg(feature = "tracing")]
+		let traced = $self.machine.inspect()
+			.map_or(true, |(opcode, _)| $self.trace_filter.contains(opcode));
+
 		if let Some((opcode, stack)) = $self.machine.inspect() {
-			event!(Step {
-				context: &$self.context,
-				opcode,
-				position: $self.machine.position(),
-				stack,
-				memory: $self.machine.memory()
-			});
+			#[cfg(feature = "tracing")]
+			if traced {
+				event!(Step {
+					context: &$self.context,
+					opcode,
+					position: $self.machine.position(),
+					stack,
+					memory: $self.machine.memory()
+				});
+			}
 
 			match $handler.pre_validate(&$self.context, opcode, stack) {
 				Ok(()) => (),
@@ -64,14 +71,30 @@ macro_rules! step {
 			},
 		}
 
+		#[cfg(feature = "tracing")]
+		let stepped = match ($self.machine.position(), $self.machine.inspect()) {
+			(Ok(pc), Some((opcode, _))) => Some((*pc, opcode)),
+			_ => None,
+		};
+
 		let result = $self.machine.step();
 
-		event!(StepResult {
-			result: &result,
-			return_value: &$self.machine.return_value(),
-		});
+		#[cfg(feature = "tracing")]
+		if traced {
+			event!(StepResult {
+				result: &result,
+				return_value: &$self.machine.return_value(),
+				info: stepped.map(|(pc, opcode)| crate::tracing::StepInfo {
+					pc,
+					opcode,
+					stack_len_after: $self.machine.stack().len(),
+					memory_len_after: $self.machine.memory().effective_len(),
+				}),
+			});
+		}
 
 		match result {
+			#[allow(clippy::double_parens)]
 			Ok(()) => $($ok)?(()),
 			Err(Capture::Exit(e)) => {
 				$self.status = Err(e.clone());
@@ -80,6 +103,7 @@ macro_rules! step {
 			},
 			Err(Capture::Trap(opcode)) => {
 				match eval::eval($self, opcode, $handler) {
+					#[allow(clippy::double_parens)]
 					eval::Control::Continue => $($ok)?(()),
 					eval::Control::CallInterrupt(interrupt) => {
 						let resolve = ResolveCall::new($self);
@@ -111,14 +135,17 @@ pub struct Runtime<'config> {
 	status: Result<(), ExitReason>,
 	return_data_buffer: Vec<u8>,
 	context: Context,
+	chain_id: Option<U256>,
+	#[cfg(feature = "tracing")]
+	trace_filter: tracing::TraceFilter,
 	_config: &'config Config,
 }
 
 impl<'config> Runtime<'config> {
 	/// Create a new runtime with given code and data.
 	pub fn new(
-		code: Rc<Vec<u8>>,
-		data: Rc<Vec<u8>>,
+		code: Shared<Vec<u8>>,
+		data: Shared<Vec<u8>>,
 		context: Context,
 		config: &'config Config,
 	) -> Self {
@@ -127,6 +154,9 @@ impl<'config> Runtime<'config> {
 			status: Ok(()),
 			return_data_buffer: Vec::new(),
 			context,
+			chain_id: None,
+			#[cfg(feature = "tracing")]
+			trace_filter: tracing::TraceFilter::default(),
 			_config: config,
 		}
 	}
@@ -141,7 +171,51 @@ impl<'config> Runtime<'config> {
 		&self.context
 	}
 
-	/// Step the runtime.
+	/// Charge memory growth of this runtime to a budget shared with other
+	/// frames. See `Machine::set_memory_budget`.
+	pub fn set_memory_budget(&mut self, budget: Shared<MemoryBudget>) -> Result<(), ExitError> {
+		self.machine.set_memory_budget(budget)
+	}
+
+	/// Only emit `Step` and `StepResult` events for opcodes in `filter`. Other
+	/// events, such as `SLoad` and `SStore`, are not affected.
+	#[cfg(feature = "tracing")]
+	pub fn set_trace_filter(&mut self, filter: tracing::TraceFilter) {
+		self.trace_filter = filter;
+	}
+
+	/// Set the chain ID served by `CHAINID`. If not set, the cache is filled
+	/// lazily rather than at construction, since `Runtime::new` has no handler:
+	/// the first `CHAINID` fetches it from the handler, and later ones reuse
+	/// it. Setting it here avoids even that first call.
+	pub fn set_chain_id(&mut self, chain_id: U256) {
+		self.chain_id = Some(chain_id);
+	}
+
+	/// Read the operands a call of `scheme` would take from the stack, without
+	/// popping them or running the call. Returns `None` if the stack is too
+	/// short.
+	pub fn peek_call_args(&self, scheme: CallScheme) -> Option<CallArgs> {
+		let stack = self.machine.stack();
+		let (value, rest) = match scheme {
+			CallScheme::Call | CallScheme::CallCode => (stack.peek(2).ok()?, 3),
+			CallScheme::DelegateCall | CallScheme::StaticCall => (U256::zero(), 2),
+		};
+
+		Some(CallArgs {
+			gas: stack.peek(0).ok()?,
+			target: stack.peek_h256(1).ok()?.into(),
+			value,
+			in_offset: stack.peek(rest).ok()?,
+			in_len: stack.peek(rest + 1).ok()?,
+			out_offset: stack.peek(rest + 2).ok()?,
+			out_len: stack.peek(rest + 3).ok()?,
+		})
+	}
+
+	/// Step the runtime, executing one opcode like `Machine::step`. External
+	/// opcodes are evaluated against `handler` within the same step; calls and
+	/// creates the handler interrupts come back as a `Resolve`.
 	pub fn step<'a, H: Handler>(
 		&'a mut self,
 		handler: &mut H,
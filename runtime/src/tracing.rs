@@ -1,7 +1,10 @@
 //! Allows to listen to runtime events.
 
 use crate::{Capture, Context, ExitReason, Memory, Opcode, Stack, Trap};
-use primitive_types::{H160, H256};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use primitive_types::{H160, H256, U256};
 
 environmental::environmental!(listener: dyn EventListener + 'static);
 
@@ -21,6 +24,8 @@ pub enum Event<'a> {
 	StepResult {
 		result: &'a Result<(), Capture<ExitReason, Trap>>,
 		return_value: &'a [u8],
+		/// The step that ran, or `None` if the machine had nothing to run.
+		info: Option<StepInfo>,
 	},
 	SLoad {
 		address: H160,
@@ -34,6 +39,146 @@ pub enum Event<'a> {
 	},
 }
 
+/// Summary of a step that ran, attached to `Event::StepResult`. The sizes are
+/// those the step left behind; `Event::Step` carries the state before it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StepInfo {
+	/// Program counter of the opcode.
+	pub pc: usize,
+	/// The opcode.
+	pub opcode: Opcode,
+	/// Stack length after the step.
+	pub stack_len_after: usize,
+	/// Effective memory length after the step, as reported by `MSIZE`.
+	pub memory_len_after: U256,
+}
+
+/// Set of opcodes whose steps are traced. The default traces every opcode.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TraceFilter([u64; 4]);
+
+impl TraceFilter {
+	/// Trace every opcode.
+	pub const fn all() -> Self {
+		Self([u64::MAX; 4])
+	}
+
+	/// Trace no opcode.
+	pub const fn none() -> Self {
+		Self([0; 4])
+	}
+
+	/// Trace only the given opcodes.
+	pub fn only(opcodes: &[Opcode]) -> Self {
+		opcodes
+			.iter()
+			.fold(Self::none(), |filter, opcode| filter.with(*opcode))
+	}
+
+	/// Also trace `opcode`.
+	pub fn with(mut self, opcode: Opcode) -> Self {
+		self.0[opcode.as_usize() / 64] |= 1 << (opcode.as_usize() % 64);
+		self
+	}
+
+	/// Whether steps of `opcode` are traced.
+	pub fn contains(&self, opcode: Opcode) -> bool {
+		self.0[opcode.as_usize() / 64] & (1 << (opcode.as_usize() % 64)) != 0
+	}
+}
+
+impl Default for TraceFilter {
+	fn default() -> Self {
+		Self::all()
+	}
+}
+
+/// One step of an execution log, taken before the opcode runs.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceLine {
+	/// Program counter of the opcode.
+	pub pc: usize,
+	/// The opcode.
+	pub opcode: Opcode,
+	/// The top of the stack, bottom first so the last item is the top.
+	pub stack: Vec<U256>,
+	/// Memory size as `MSIZE` reports it.
+	pub memory_size: U256,
+}
+
+/// Listener recording `Step` events as `TraceLine`s, giving a canonical log
+/// to diff against other VMs. Steps of an exited machine are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct TraceRecorder {
+	stack_depth: usize,
+	lines: Vec<TraceLine>,
+}
+
+impl TraceRecorder {
+	/// Create a recorder keeping at most `stack_depth` items off the top of
+	/// the stack for each step.
+	pub fn new(stack_depth: usize) -> Self {
+		Self {
+			stack_depth,
+			lines: Vec::new(),
+		}
+	}
+
+	/// The steps recorded so far.
+	pub fn lines(&self) -> &[TraceLine] {
+		&self.lines
+	}
+
+	/// Take the recorded steps.
+	pub fn into_lines(self) -> Vec<TraceLine> {
+		self.lines
+	}
+
+	/// Format the steps as JSON lines, one object per step, such as
+	/// `{"pc":0,"op":96,"opName":"PUSH1","stack":[],"memSize":0}`. Stack items
+	/// are `0x`-prefixed hex.
+	pub fn to_jsonl(&self) -> String {
+		let mut out = String::new();
+		for line in &self.lines {
+			let _ = write!(
+				out,
+				"{{\"pc\":{},\"op\":{},\"opName\":\"{}\",\"stack\":[",
+				line.pc,
+				line.opcode.as_u8(),
+				line.opcode,
+			);
+			for (i, item) in line.stack.iter().enumerate() {
+				let separator = if i == 0 { "" } else { "," };
+				let _ = write!(out, "{}\"0x{:x}\"", separator, item);
+			}
+			let _ = writeln!(out, "],\"memSize\":{}}}", line.memory_size);
+		}
+		out
+	}
+}
+
+impl EventListener for TraceRecorder {
+	fn event(&mut self, event: Event) {
+		if let Event::Step {
+			opcode,
+			position: Ok(position),
+			stack,
+			memory,
+			..
+		} = event
+		{
+			let items = stack.data();
+			let start = items.len().saturating_sub(self.stack_depth);
+			self.lines.push(TraceLine {
+				pc: *position,
+				opcode,
+				stack: items[start..].to_vec(),
+				memory_size: memory.effective_len(),
+			});
+		}
+	}
+}
+
 // Expose `listener::with` to the crate only.
 pub(crate) fn with<F: FnOnce(&mut (dyn EventListener + 'static))>(f: F) {
 	listener::with(f);
@@ -43,3 +188,91 @@ pub(crate) fn with<F: FnOnce(&mut (dyn EventListener + 'static))>(f: F) {
 pub fn using<R, F: FnOnce() -> R>(new: &mut (dyn EventListener + 'static), f: F) -> R {
 	listener::using(new, f)
 }
+
+/// Compact binary sink for `Step` events.
+///
+/// Every step is written as a varint length prefix followed by the record: the
+/// program counter as a varint, the opcode byte, and a flag byte that is `1`
+/// when the 32-byte big-endian stack top follows and `0` otherwise. Other
+/// events and steps of an exited machine are skipped.
+#[cfg(feature = "std")]
+pub struct BinaryTraceWriter<W: std::io::Write> {
+	writer: W,
+	stack_top: bool,
+	record: Vec<u8>,
+	error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> BinaryTraceWriter<W> {
+	/// Create a new writer. If `stack_top` is set, the top of the stack is
+	/// recorded for each step.
+	pub fn new(writer: W, stack_top: bool) -> Self {
+		Self {
+			writer,
+			stack_top,
+			record: Vec::new(),
+			error: None,
+		}
+	}
+
+	/// Take the first I/O error encountered, if any. Once an error occurs no
+	/// further records are written.
+	pub fn take_error(&mut self) -> Option<std::io::Error> {
+		self.error.take()
+	}
+
+	/// Unwrap the underlying writer.
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> EventListener for BinaryTraceWriter<W> {
+	fn event(&mut self, event: Event) {
+		if self.error.is_some() {
+			return;
+		}
+
+		if let Event::Step {
+			opcode,
+			position: Ok(position),
+			stack,
+			..
+		} = event
+		{
+			self.record.clear();
+			write_varint(&mut self.record, *position as u64);
+			self.record.push(opcode.as_u8());
+			match stack.peek(0) {
+				Ok(top) if self.stack_top => {
+					let mut buf = [0u8; 32];
+					top.to_big_endian(&mut buf);
+					self.record.push(1);
+					self.record.extend_from_slice(&buf);
+				}
+				_ => self.record.push(0),
+			}
+
+			let mut prefix = Vec::with_capacity(2);
+			write_varint(&mut prefix, self.record.len() as u64);
+			if let Err(e) = self
+				.writer
+				.write_all(&prefix)
+				.and_then(|()| self.writer.write_all(&self.record))
+			{
+				self.error = Some(e);
+			}
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+	while value >= 0x80 {
+		out.push((value as u8) | 0x80);
+		value >>= 7;
+	}
+	out.push(value as u8);
+}
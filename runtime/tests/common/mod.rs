@@ -0,0 +1,249 @@
+#![allow(dead_code)]
+
+use evm_runtime::{
+	Capture, Config, Context, CreateScheme, ExitError, ExitReason, ExitSucceed, Handler, Machine,
+	Opcode, Runtime, Shared, Stack, Transfer,
+};
+use primitive_types::{H160, H256, U256};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+/// Address the test code runs at.
+pub const ADDRESS: H160 = H160([0x11; 20]);
+/// Caller of the test code.
+pub const CALLER: H160 = H160([0x22; 20]);
+
+/// A sub-call observed by the mock handler.
+#[derive(Clone, Debug)]
+pub struct Call {
+	pub code_address: H160,
+	pub transfer: Option<Transfer>,
+	pub input: Vec<u8>,
+	pub target_gas: Option<u64>,
+	pub is_static: bool,
+	pub context: Context,
+}
+
+/// A create observed by the mock handler.
+#[derive(Clone, Debug)]
+pub struct Create {
+	pub caller: H160,
+	pub scheme: CreateScheme,
+	pub value: U256,
+	pub init_code: Vec<u8>,
+	pub target_gas: Option<u64>,
+}
+
+/// In-memory handler recording what the runtime asks of it.
+pub struct MockHandler {
+	pub codes: BTreeMap<H160, Vec<u8>>,
+	pub logs: Vec<(H160, Vec<H256>, Vec<u8>)>,
+	pub calls: Vec<Call>,
+	pub creates: Vec<Create>,
+	pub others: Vec<Opcode>,
+	pub warmed: Vec<H160>,
+	pub call_result: (ExitReason, Vec<u8>),
+	pub chain_id: U256,
+	pub base_fee: Option<U256>,
+	pub chain_id_calls: Cell<usize>,
+	pub queried: RefCell<Vec<H160>>,
+	pub gas_left: u64,
+	pub gas_per_opcode: u64,
+	pub code_size_override: Option<U256>,
+}
+
+impl Default for MockHandler {
+	fn default() -> Self {
+		Self {
+			codes: BTreeMap::new(),
+			logs: Vec::new(),
+			calls: Vec::new(),
+			creates: Vec::new(),
+			others: Vec::new(),
+			warmed: Vec::new(),
+			call_result: (ExitSucceed::Returned.into(), Vec::new()),
+			chain_id: U256::one(),
+			base_fee: Some(U256::zero()),
+			chain_id_calls: Cell::new(0),
+			queried: RefCell::new(Vec::new()),
+			gas_left: 0,
+			gas_per_opcode: 0,
+			code_size_override: None,
+		}
+	}
+}
+
+impl Handler for MockHandler {
+	type CreateInterrupt = Infallible;
+	type CreateFeedback = Infallible;
+	type CallInterrupt = Infallible;
+	type CallFeedback = Infallible;
+
+	fn balance(&self, address: H160) -> U256 {
+		self.queried.borrow_mut().push(address);
+		U256::zero()
+	}
+	fn code_size(&self, address: H160) -> U256 {
+		self.queried.borrow_mut().push(address);
+		if let Some(size) = self.code_size_override {
+			return size;
+		}
+		U256::from(self.codes.get(&address).map(|c| c.len()).unwrap_or(0))
+	}
+	fn code_hash(&self, address: H160) -> H256 {
+		self.queried.borrow_mut().push(address);
+		H256::default()
+	}
+	fn code(&self, address: H160) -> Vec<u8> {
+		self.queried.borrow_mut().push(address);
+		self.codes.get(&address).cloned().unwrap_or_default()
+	}
+	fn storage(&self, _address: H160, _index: H256) -> H256 {
+		H256::default()
+	}
+
+	fn gas_left(&self) -> U256 {
+		U256::from(self.gas_left)
+	}
+	fn gas_price(&self) -> U256 {
+		U256::zero()
+	}
+	fn origin(&self) -> H160 {
+		CALLER
+	}
+	fn block_hash(&self, number: U256) -> H256 {
+		H256::from_low_u64_be(number.low_u64())
+	}
+	fn block_number(&self) -> U256 {
+		U256::zero()
+	}
+	fn block_coinbase(&self) -> H160 {
+		H160::default()
+	}
+	fn block_timestamp(&self) -> U256 {
+		U256::zero()
+	}
+	fn block_difficulty(&self) -> U256 {
+		U256::zero()
+	}
+	fn block_gas_limit(&self) -> U256 {
+		U256::zero()
+	}
+	fn block_base_fee_per_gas(&self) -> U256 {
+		self.base_fee.expect("base fee queried while unsupported")
+	}
+	fn supports_base_fee(&self) -> bool {
+		self.base_fee.is_some()
+	}
+	fn chain_id(&self) -> U256 {
+		self.chain_id_calls.set(self.chain_id_calls.get() + 1);
+		self.chain_id
+	}
+
+	fn exists(&self, _address: H160) -> bool {
+		true
+	}
+	fn deleted(&self, _address: H160) -> bool {
+		false
+	}
+	fn is_cold(&self, _address: H160, _index: Option<H256>) -> bool {
+		false
+	}
+	fn warm_address(&mut self, address: H160) {
+		self.warmed.push(address);
+	}
+
+	fn set_storage(&mut self, _address: H160, _index: H256, _value: H256) -> Result<(), ExitError> {
+		Ok(())
+	}
+	fn log(&mut self, address: H160, topics: Vec<H256>, data: Vec<u8>) -> Result<(), ExitError> {
+		self.logs.push((address, topics, data));
+		Ok(())
+	}
+	fn mark_delete(&mut self, _address: H160, _target: H160) -> Result<(), ExitError> {
+		Ok(())
+	}
+	fn create(
+		&mut self,
+		caller: H160,
+		scheme: CreateScheme,
+		value: U256,
+		init_code: Vec<u8>,
+		target_gas: Option<u64>,
+	) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Self::CreateInterrupt> {
+		self.creates.push(Create {
+			caller,
+			scheme,
+			value,
+			init_code,
+			target_gas,
+		});
+		Capture::Exit((ExitSucceed::Returned.into(), None, Vec::new()))
+	}
+	fn call(
+		&mut self,
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<u64>,
+		is_static: bool,
+		context: Context,
+	) -> Capture<(ExitReason, Vec<u8>), Self::CallInterrupt> {
+		self.calls.push(Call {
+			code_address,
+			transfer,
+			input,
+			target_gas,
+			is_static,
+			context,
+		});
+		Capture::Exit(self.call_result.clone())
+	}
+
+	fn pre_validate(
+		&mut self,
+		_context: &Context,
+		_opcode: Opcode,
+		_stack: &Stack,
+	) -> Result<(), ExitError> {
+		self.gas_left = self
+			.gas_left
+			.checked_sub(self.gas_per_opcode)
+			.ok_or(ExitError::OutOfGas)?;
+		Ok(())
+	}
+	fn other(&mut self, opcode: Opcode, machine: &mut Machine) -> Result<(), ExitError> {
+		self.others.push(opcode);
+		machine.stack_mut().push(U256::from(opcode.as_u8()))
+	}
+}
+
+/// Default context the test code runs in.
+pub fn context() -> Context {
+	Context {
+		address: ADDRESS,
+		caller: CALLER,
+		apparent_value: U256::zero(),
+	}
+}
+
+/// Run `code` to completion against `handler`, returning the exit reason and
+/// the final runtime.
+pub fn run<'config>(
+	code: Vec<u8>,
+	config: &'config Config,
+	handler: &mut MockHandler,
+) -> (ExitReason, Runtime<'config>) {
+	let mut runtime = Runtime::new(
+		Shared::new(code),
+		Shared::new(Vec::new()),
+		context(),
+		config,
+	);
+	let reason = match runtime.run(handler) {
+		Capture::Exit(reason) => reason,
+		Capture::Trap(_) => unreachable!("mock handler never interrupts"),
+	};
+	(reason, runtime)
+}
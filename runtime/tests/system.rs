@@ -0,0 +1,596 @@
+mod common;
+
+use common::{context, run, MockHandler, ADDRESS, CALLER};
+use evm_runtime::{
+	AccessList, CallArgs, CallScheme, Capture, Config, CreateScheme, ExitError, ExitReason,
+	ExitRevert, ExitSucceed, Handler, Opcode, Runtime, Shared,
+};
+use primitive_types::{H160, H256, U256};
+use sha3::{Digest, Keccak256};
+
+static CONFIG: Config = Config::london();
+
+#[test]
+fn chainid_is_fetched_once() {
+	let mut handler = MockHandler {
+		chain_id: U256::from(1337),
+		..Default::default()
+	};
+
+	// CHAINID CHAINID CHAINID POP POP PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+	let code = vec![
+		0x46, 0x46, 0x46, 0x50, 0x50, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+	];
+	let (reason, runtime) = run(code, &CONFIG, &mut handler);
+
+	assert_eq!(reason, ExitSucceed::Returned.into());
+	assert_eq!(
+		runtime.machine().return_value(),
+		H256::from_low_u64_be(1337).as_bytes()
+	);
+	assert_eq!(handler.chain_id_calls.get(), 1);
+
+	// Code without CHAINID never asks the handler.
+	let mut handler = MockHandler::default();
+	let (reason, _) = run(vec![0x00], &CONFIG, &mut handler);
+	assert_eq!(reason, ExitSucceed::Stopped.into());
+	assert_eq!(handler.chain_id_calls.get(), 0);
+}
+
+#[test]
+fn chainid_served_from_runtime() {
+	let mut handler = MockHandler::default();
+	// CHAINID PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+	let code = vec![0x46, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+	let mut runtime = Runtime::new(
+		Shared::new(code),
+		Shared::new(Vec::new()),
+		context(),
+		&CONFIG,
+	);
+	runtime.set_chain_id(U256::from(42));
+
+	assert!(matches!(
+		runtime.run(&mut handler),
+		Capture::Exit(reason) if reason == ExitSucceed::Returned.into()
+	));
+
+	assert_eq!(
+		runtime.machine().return_value(),
+		H256::from_low_u64_be(42).as_bytes()
+	);
+	assert_eq!(handler.chain_id_calls.get(), 0);
+}
+
+#[test]
+fn log_topics_follow_stack_order() {
+	for n in 0..=4u8 {
+		let mut handler = MockHandler::default();
+
+		// PUSH1 n .. PUSH1 1 PUSH1 0 PUSH1 0 LOGn
+		let mut code = Vec::new();
+		for topic in (1..=n).rev() {
+			code.extend_from_slice(&[0x60, topic]);
+		}
+		code.extend_from_slice(&[0x60, 0x00, 0x60, 0x00, 0xa0 + n]);
+		let (reason, _) = run(code, &CONFIG, &mut handler);
+
+		assert_eq!(reason, ExitSucceed::Stopped.into());
+		let expected = (1..=n)
+			.map(|topic| H256::from_low_u64_be(topic as u64))
+			.collect::<Vec<_>>();
+		assert_eq!(handler.logs, vec![(ADDRESS, expected, Vec::new())]);
+	}
+}
+
+#[test]
+fn gas_reports_value_after_own_cost() {
+	let mut handler = MockHandler {
+		gas_left: 100,
+		gas_per_opcode: 2,
+		..Default::default()
+	};
+
+	// GAS
+	let (reason, runtime) = run(vec![0x5a], &CONFIG, &mut handler);
+
+	assert_eq!(reason, ExitSucceed::Stopped.into());
+	assert_eq!(runtime.machine().stack().peek(0), Ok(U256::from(98)));
+}
+
+#[test]
+fn pre_validate_error_halts_before_opcode() {
+	let mut handler = MockHandler {
+		gas_left: 5,
+		gas_per_opcode: 2,
+		..Default::default()
+	};
+
+	// PUSH1 1 PUSH1 2 PUSH1 3
+	let code = vec![0x60, 0x01, 0x60, 0x02, 0x60, 0x03];
+	let (reason, runtime) = run(code, &CONFIG, &mut handler);
+
+	assert_eq!(reason, ExitError::OutOfGas.into());
+	assert_eq!(handler.gas_left, 1);
+	assert_eq!(
+		runtime.machine().stack().data(),
+		&vec![U256::one(), U256::from(2)]
+	);
+}
+
+#[test]
+fn basefee_depends_on_handler_support() {
+	let mut handler = MockHandler {
+		base_fee: Some(U256::from(7)),
+		..Default::default()
+	};
+	// BASEFEE
+	let (reason, runtime) = run(vec![0x48], &CONFIG, &mut handler);
+	assert_eq!(reason, ExitSucceed::Stopped.into());
+	assert_eq!(runtime.machine().stack().peek(0), Ok(U256::from(7)));
+
+	let mut handler = MockHandler {
+		base_fee: None,
+		..Default::default()
+	};
+	let (reason, runtime) = run(vec![0x48], &CONFIG, &mut handler);
+	assert_eq!(reason, ExitError::InvalidOpcode.into());
+	assert!(runtime.machine().stack().is_empty());
+}
+
+/// PUSH1 len PUSH1 offset PUSH1 0 CODECOPY PUSH1 64 PUSH1 0 RETURN
+fn codecopy_program(offset: u8, len: u8) -> Vec<u8> {
+	vec![
+		0x60, len, 0x60, offset, 0x60, 0x00, 0x39, 0x60, 0x40, 0x60, 0x00, 0xf3,
+	]
+}
+
+#[test]
+fn codecopy_zero_pads_like_extcodecopy() {
+	// Entire code, starting past the end, spanning the end, and nothing.
+	for &(offset, len) in &[(0, 12), (20, 8), (8, 8), (0, 0)] {
+		let program = codecopy_program(offset, len);
+		let mut expected = (offset..(offset + len))
+			.map(|i| program.get(i as usize).cloned().unwrap_or(0))
+			.collect::<Vec<_>>();
+		expected.resize(64, 0);
+
+		let mut handler = MockHandler::default();
+		let (reason, runtime) = run(program.clone(), &CONFIG, &mut handler);
+		assert_eq!(reason, ExitSucceed::Returned.into());
+		assert_eq!(runtime.machine().return_value(), expected);
+
+		// PUSH1 len PUSH1 offset PUSH1 0 PUSH20 ADDRESS EXTCODECOPY
+		// PUSH1 64 PUSH1 0 RETURN
+		let mut code = vec![0x60, len, 0x60, offset, 0x60, 0x00, 0x73];
+		code.extend_from_slice(ADDRESS.as_bytes());
+		code.extend_from_slice(&[0x3c, 0x60, 0x40, 0x60, 0x00, 0xf3]);
+		let mut handler = MockHandler::default();
+		handler.codes.insert(ADDRESS, program);
+		let (reason, runtime) = run(code, &CONFIG, &mut handler);
+		assert_eq!(reason, ExitSucceed::Returned.into());
+		assert_eq!(runtime.machine().return_value(), expected);
+	}
+}
+
+#[test]
+fn address_opcodes_take_low_20_bytes() {
+	let target = H160::repeat_byte(0x33);
+	let mut word = [0xee; 32];
+	word[12..].copy_from_slice(target.as_bytes());
+
+	let mut code = Vec::new();
+	// PUSH32 word BALANCE POP, then likewise EXTCODESIZE and EXTCODEHASH
+	for opcode in &[0x31, 0x3b, 0x3f] {
+		code.push(0x7f);
+		code.extend_from_slice(&word);
+		code.extend_from_slice(&[*opcode, 0x50]);
+	}
+	// PUSH1 0 PUSH1 0 PUSH1 0 PUSH32 word EXTCODECOPY
+	code.extend_from_slice(&[0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x7f]);
+	code.extend_from_slice(&word);
+	code.push(0x3c);
+
+	let mut handler = MockHandler::default();
+	let (reason, _) = run(code, &CONFIG, &mut handler);
+
+	assert_eq!(reason, ExitSucceed::Stopped.into());
+	// The code size check queries both code and its size once more.
+	let queries = if cfg!(feature = "debug-handler") {
+		8
+	} else {
+		4
+	};
+	assert_eq!(*handler.queried.borrow(), vec![target; queries]);
+}
+
+#[test]
+fn block_hash_range_defaults_to_block_hash() {
+	let handler = MockHandler::default();
+
+	assert_eq!(
+		handler.block_hash_range(U256::from(5), 3),
+		vec![
+			H256::from_low_u64_be(5),
+			H256::from_low_u64_be(6),
+			H256::from_low_u64_be(7)
+		]
+	);
+	assert!(handler.block_hash_range(U256::from(5), 0).is_empty());
+	assert_eq!(handler.block_hash_range(U256::MAX, 2).len(), 1);
+}
+
+/// Run a single call of `opcode` to `target` passing `value` when the scheme
+/// takes one, from a frame whose apparent value is 9, and return what the
+/// handler saw.
+fn call_once(opcode: u8, target: H160) -> common::Call {
+	// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 [PUSH1 5] PUSH20 target PUSH1 0xff <opcode>
+	let mut code = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00];
+	if opcode == 0xf1 || opcode == 0xf2 {
+		code.extend_from_slice(&[0x60, 0x05]);
+	}
+	code.push(0x73);
+	code.extend_from_slice(target.as_bytes());
+	code.extend_from_slice(&[0x60, 0xff, opcode]);
+
+	let mut handler = MockHandler::default();
+	let mut context = context();
+	context.apparent_value = U256::from(9);
+	let mut runtime = Runtime::new(Shared::new(code), Shared::new(Vec::new()), context, &CONFIG);
+	assert!(matches!(
+		runtime.run(&mut handler),
+		Capture::Exit(reason) if reason == ExitSucceed::Stopped.into()
+	));
+
+	assert_eq!(handler.calls.len(), 1);
+	// Warming the target is left to the handler's `pre_validate`.
+	assert!(handler.warmed.is_empty());
+	handler.calls.pop().unwrap()
+}
+
+#[test]
+fn call_schemes_build_context() {
+	let target = H160::repeat_byte(0x44);
+
+	// CALL
+	let call = call_once(0xf1, target);
+	assert_eq!(call.code_address, target);
+	assert_eq!(call.context.address, target);
+	assert_eq!(call.context.caller, ADDRESS);
+	assert_eq!(call.context.apparent_value, U256::from(5));
+	let transfer = call.transfer.unwrap();
+	assert_eq!((transfer.source, transfer.target), (ADDRESS, target));
+	assert_eq!(transfer.value, U256::from(5));
+	assert!(!call.is_static);
+
+	// CALLCODE runs the target's code as the current contract.
+	let call = call_once(0xf2, target);
+	assert_eq!(call.code_address, target);
+	assert_eq!(call.context.address, ADDRESS);
+	assert_eq!(call.context.caller, ADDRESS);
+	assert_eq!(call.context.apparent_value, U256::from(5));
+	let transfer = call.transfer.unwrap();
+	assert_eq!((transfer.source, transfer.target), (ADDRESS, ADDRESS));
+	assert_eq!(transfer.value, U256::from(5));
+
+	// DELEGATECALL keeps the caller and value of the current frame.
+	let call = call_once(0xf4, target);
+	assert_eq!(call.code_address, target);
+	assert_eq!(call.context.address, ADDRESS);
+	assert_eq!(call.context.caller, CALLER);
+	assert_eq!(call.context.apparent_value, U256::from(9));
+	assert!(call.transfer.is_none());
+
+	// STATICCALL
+	let call = call_once(0xfa, target);
+	assert_eq!(call.context.address, target);
+	assert_eq!(call.context.caller, ADDRESS);
+	assert_eq!(call.context.apparent_value, U256::zero());
+	assert!(call.transfer.is_none());
+	assert!(call.is_static);
+}
+
+#[test]
+fn create_passes_scheme_and_init_code() {
+	for &(opcode, salted) in &[(0xf0, false), (0xf5, true)] {
+		let mut handler = MockHandler::default();
+
+		// PUSH1 0xab PUSH1 0 MSTORE8 [PUSH1 7] PUSH1 1 PUSH1 0 PUSH1 3 <opcode>
+		let mut code = vec![0x60, 0xab, 0x60, 0x00, 0x53];
+		if salted {
+			code.extend_from_slice(&[0x60, 0x07]);
+		}
+		code.extend_from_slice(&[0x60, 0x01, 0x60, 0x00, 0x60, 0x03, opcode]);
+		let (reason, _) = run(code, &CONFIG, &mut handler);
+		assert_eq!(reason, ExitSucceed::Stopped.into());
+
+		assert_eq!(handler.creates.len(), 1);
+		let create = &handler.creates[0];
+		assert_eq!(create.caller, ADDRESS);
+		assert_eq!(create.value, U256::from(3));
+		assert_eq!(create.init_code, vec![0xab]);
+		// The gas to forward is left to the handler.
+		assert_eq!(create.target_gas, None);
+		let scheme = if salted {
+			CreateScheme::Create2 {
+				caller: ADDRESS,
+				code_hash: H256::from_slice(&Keccak256::digest(&[0xab])),
+				salt: H256::from_low_u64_be(7),
+			}
+		} else {
+			CreateScheme::Legacy { caller: ADDRESS }
+		};
+		assert_eq!(create.scheme, scheme);
+	}
+}
+
+#[test]
+fn returndatacopy_boundaries() {
+	let returned = vec![1, 2, 3, 4];
+
+	for &(offset, len, ok) in &[(0, 4, true), (1, 3, true), (4, 0, true), (4, 1, false)] {
+		let mut handler = MockHandler {
+			call_result: (ExitSucceed::Returned.into(), returned.clone()),
+			..Default::default()
+		};
+
+		// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 CALLER PUSH1 0xff STATICCALL POP
+		let mut code = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73];
+		code.extend_from_slice(CALLER.as_bytes());
+		code.extend_from_slice(&[0x60, 0xff, 0xfa, 0x50]);
+		// PUSH1 len PUSH1 offset PUSH1 0 RETURNDATACOPY PUSH1 32 PUSH1 0 RETURN
+		code.extend_from_slice(&[0x60, len, 0x60, offset, 0x60, 0x00, 0x3e]);
+		code.extend_from_slice(&[0x60, 0x20, 0x60, 0x00, 0xf3]);
+		let (reason, runtime) = run(code, &CONFIG, &mut handler);
+
+		if ok {
+			assert_eq!(reason, ExitSucceed::Returned.into());
+			let mut expected = returned[offset as usize..(offset + len) as usize].to_vec();
+			expected.resize(32, 0);
+			assert_eq!(runtime.machine().return_value(), expected);
+		} else {
+			assert_eq!(reason, ExitError::OutOfOffset.into());
+		}
+	}
+}
+
+#[test]
+fn prewarm_defaults_to_warm_address() {
+	let a = H160::repeat_byte(0xaa);
+	let b = H160::repeat_byte(0xbb);
+	let list = AccessList::from(vec![
+		(a, vec![H256::repeat_byte(1), H256::repeat_byte(2)]),
+		(b, Vec::new()),
+	]);
+	assert_eq!(list.addresses().collect::<Vec<_>>(), vec![a, b]);
+	assert_eq!(
+		list.storage_keys().collect::<Vec<_>>(),
+		vec![(a, H256::repeat_byte(1)), (a, H256::repeat_byte(2))]
+	);
+
+	let mut handler = MockHandler::default();
+	handler.prewarm(&list);
+	assert_eq!(handler.warmed, vec![a, b]);
+}
+
+#[test]
+fn returndata_is_replaced_by_each_call() {
+	let mut handler = MockHandler {
+		call_result: (ExitSucceed::Returned.into(), vec![1, 2, 3, 4]),
+		..Default::default()
+	};
+
+	// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 CALLER PUSH1 0xff CALL POP
+	// RETURNDATASIZE, three times over.
+	let mut call = vec![
+		0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+	];
+	call.extend_from_slice(CALLER.as_bytes());
+	call.extend_from_slice(&[0x60, 0xff, 0xf1, 0x50, 0x3d]);
+	let mut runtime = Runtime::new(
+		Shared::new(call.repeat(3)),
+		Shared::new(Vec::new()),
+		context(),
+		&CONFIG,
+	);
+
+	let mut returndatasize_after = |handler: &mut MockHandler| {
+		for _ in 0..10 {
+			assert!(runtime.step(handler).is_ok());
+		}
+		runtime.machine().stack().peek(0).unwrap()
+	};
+
+	assert_eq!(returndatasize_after(&mut handler), U256::from(4));
+
+	// A reverted call replaces the buffer with its own data.
+	handler.call_result = (ExitRevert::Reverted.into(), vec![5, 6]);
+	assert_eq!(returndatasize_after(&mut handler), U256::from(2));
+
+	// A failed call leaves nothing behind, not the previous call's data.
+	handler.call_result = (ExitError::OutOfGas.into(), Vec::new());
+	assert_eq!(returndatasize_after(&mut handler), U256::zero());
+	assert_eq!(handler.calls.len(), 3);
+}
+
+#[cfg(feature = "debug-handler")]
+#[test]
+#[should_panic(expected = "handler code_size disagrees with code")]
+fn inconsistent_code_size_is_caught() {
+	let mut handler = MockHandler {
+		code_size_override: Some(U256::from(1)),
+		..Default::default()
+	};
+
+	// PUSH20 CALLER EXTCODESIZE
+	let mut code = vec![0x73];
+	code.extend_from_slice(CALLER.as_bytes());
+	code.push(0x3b);
+	run(code, &CONFIG, &mut handler);
+}
+
+#[test]
+fn returndatacopy_rejects_overflowing_ranges() {
+	let max = U256::MAX;
+	let cases = [
+		// (memory_offset, data_offset, len, expected)
+		(U256::zero(), max, U256::one(), ExitError::OutOfOffset),
+		// Memory is resized before the returndata range is checked.
+		(U256::zero(), U256::one(), max, ExitError::InvalidRange),
+		(U256::zero(), max, U256::zero(), ExitError::OutOfOffset),
+		(
+			U256::zero(),
+			U256::from(5),
+			U256::zero(),
+			ExitError::OutOfOffset,
+		),
+		(max, U256::zero(), U256::one(), ExitError::InvalidRange),
+	];
+
+	for (memory_offset, data_offset, len, expected) in cases {
+		let mut handler = MockHandler {
+			call_result: (ExitSucceed::Returned.into(), vec![1, 2, 3, 4]),
+			..Default::default()
+		};
+
+		// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 CALLER PUSH1 0xff STATICCALL POP
+		let mut code = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73];
+		code.extend_from_slice(CALLER.as_bytes());
+		code.extend_from_slice(&[0x60, 0xff, 0xfa, 0x50]);
+		// PUSH32 len PUSH32 data_offset PUSH32 memory_offset RETURNDATACOPY
+		for value in &[len, data_offset, memory_offset] {
+			let mut word = [0; 32];
+			value.to_big_endian(&mut word);
+			code.push(0x7f);
+			code.extend_from_slice(&word);
+		}
+		code.push(0x3e);
+		let (reason, _) = run(code, &CONFIG, &mut handler);
+
+		assert_eq!(reason, expected.into());
+	}
+}
+
+fn unhex(hex: &str) -> Vec<u8> {
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+		.collect()
+}
+
+#[test]
+fn create_address_follows_scheme() {
+	let handler = MockHandler::default();
+
+	// EIP-1014 example 1: zero caller, salt and empty init code.
+	let create2 = CreateScheme::Create2 {
+		caller: H160::zero(),
+		code_hash: H256::from_slice(&unhex(
+			"bc36789e7a1e281436464229828f817d6612f7b477d66591ff96a9e064bcc98a",
+		)),
+		salt: H256::zero(),
+	};
+	assert_eq!(
+		create2.address(&handler),
+		H160::from_slice(&unhex("4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"))
+	);
+}
+
+#[test]
+#[should_panic(expected = "Handler::nonce")]
+fn legacy_create_address_needs_handler_nonce() {
+	// The mock handler keeps the default `nonce`.
+	CreateScheme::Legacy { caller: CALLER }.address(&MockHandler::default());
+}
+
+#[test]
+fn return_value_depends_on_exit() {
+	// PUSH1 0x2a PUSH1 0 MSTORE, then PUSH1 32 PUSH1 0 before the exit.
+	let prefix = [0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00];
+	let word = H256::from_low_u64_be(0x2a).as_bytes().to_vec();
+	let cases: [(u8, ExitReason, Vec<u8>); 5] = [
+		(0x00, ExitSucceed::Stopped.into(), Vec::new()),
+		(0xf3, ExitSucceed::Returned.into(), word.clone()),
+		(0xfd, ExitRevert::Reverted.into(), word),
+		(0xff, ExitSucceed::Suicided.into(), Vec::new()),
+		(0xfe, ExitError::DesignatedInvalid.into(), Vec::new()),
+	];
+
+	for (opcode, expected, value) in cases {
+		let mut handler = MockHandler::default();
+		let mut code = prefix.to_vec();
+		code.push(opcode);
+		let (reason, runtime) = run(code, &CONFIG, &mut handler);
+
+		assert_eq!(reason, expected);
+		assert_eq!(runtime.machine().return_value(), value);
+	}
+}
+
+#[test]
+fn peek_call_args_leaves_stack() {
+	let mut handler = MockHandler::default();
+
+	// PUSH1 4 PUSH1 3 PUSH1 2 PUSH1 1 PUSH1 9 PUSH20 CALLER PUSH2 0x1000
+	let mut code = vec![
+		0x60, 0x04, 0x60, 0x03, 0x60, 0x02, 0x60, 0x01, 0x60, 0x09, 0x73,
+	];
+	code.extend_from_slice(CALLER.as_bytes());
+	code.extend_from_slice(&[0x61, 0x10, 0x00]);
+	let (reason, runtime) = run(code, &CONFIG, &mut handler);
+	assert_eq!(reason, ExitSucceed::Stopped.into());
+
+	let call = runtime.peek_call_args(CallScheme::Call).unwrap();
+	assert_eq!(
+		call,
+		CallArgs {
+			gas: U256::from(0x1000),
+			target: CALLER,
+			value: U256::from(9),
+			in_offset: U256::from(1),
+			in_len: U256::from(2),
+			out_offset: U256::from(3),
+			out_len: U256::from(4),
+		}
+	);
+
+	// Without a value, the value slot is read as the input offset.
+	let call = runtime.peek_call_args(CallScheme::StaticCall).unwrap();
+	assert_eq!(call.value, U256::zero());
+	assert_eq!(call.in_offset, U256::from(9));
+	assert_eq!(call.out_len, U256::from(3));
+	assert_eq!(runtime.machine().stack().len(), 7);
+
+	// PUSH1 1
+	let (_, runtime) = run(vec![0x60, 0x01], &CONFIG, &mut handler);
+	assert_eq!(runtime.peek_call_args(CallScheme::StaticCall), None);
+}
+
+#[test]
+fn unknown_opcode_reaches_handler_other() {
+	let mut handler = MockHandler::default();
+
+	// PUSH1 1 0xb0 0xb1
+	let (reason, runtime) = run(vec![0x60, 0x01, 0xb0, 0xb1], &CONFIG, &mut handler);
+
+	assert_eq!(reason, ExitSucceed::Stopped.into());
+	assert_eq!(handler.others, vec![Opcode(0xb0), Opcode(0xb1)]);
+	assert_eq!(
+		runtime.machine().stack().data(),
+		&vec![U256::one(), U256::from(0xb0), U256::from(0xb1)]
+	);
+}
+
+#[test]
+fn defined_opcodes_never_reach_handler_other() {
+	// The core traps the opcodes it leaves to the runtime, CREATE included,
+	// so each defined opcode has exactly one implementation.
+	for byte in 0..=255u8 {
+		if Opcode(byte).name().is_none() {
+			continue;
+		}
+		let mut handler = MockHandler::default();
+		let _ = run(vec![byte], &CONFIG, &mut handler);
+		assert!(handler.others.is_empty(), "{}", Opcode(byte));
+	}
+}
@@ -0,0 +1,168 @@
+#![cfg(all(feature = "tracing", feature = "std"))]
+
+mod common;
+
+use common::{context, run, MockHandler};
+use evm_runtime::tracing::{
+	using, BinaryTraceWriter, Event, EventListener, StepInfo, TraceFilter, TraceRecorder,
+};
+use evm_runtime::{Config, Opcode, Runtime, Shared};
+use primitive_types::U256;
+
+static CONFIG: Config = Config::london();
+
+#[test]
+fn binary_trace_records_steps() {
+	let mut handler = MockHandler::default();
+	let mut writer = BinaryTraceWriter::new(Vec::new(), true);
+
+	// PUSH1 0x2a POP
+	using(&mut writer, || {
+		run(vec![0x60, 0x2a, 0x50], &CONFIG, &mut handler)
+	});
+
+	assert!(writer.take_error().is_none());
+	let mut expected = vec![3, 0x00, 0x60, 0x00];
+	expected.extend_from_slice(&[35, 0x02, 0x50, 0x01]);
+	expected.extend_from_slice(&[0; 31]);
+	expected.push(0x2a);
+	assert_eq!(writer.into_inner(), expected);
+}
+
+#[derive(Default)]
+struct StepInfos(Vec<Option<StepInfo>>);
+
+impl EventListener for StepInfos {
+	fn event(&mut self, event: Event) {
+		if let Event::StepResult { info, .. } = event {
+			self.0.push(info);
+		}
+	}
+}
+
+#[test]
+fn step_result_reports_sizes() {
+	let mut handler = MockHandler::default();
+	let mut infos = StepInfos::default();
+
+	// PUSH1 0x2a PUSH1 0 MSTORE
+	using(&mut infos, || {
+		run(vec![0x60, 0x2a, 0x60, 0x00, 0x52], &CONFIG, &mut handler)
+	});
+
+	let step = |pc, opcode, stack_len, memory_len| {
+		Some(StepInfo {
+			pc,
+			opcode,
+			stack_len_after: stack_len,
+			memory_len_after: U256::from(memory_len),
+		})
+	};
+	assert_eq!(
+		infos.0,
+		vec![
+			step(0, Opcode::PUSH1, 1, 0),
+			step(2, Opcode::PUSH1, 2, 0),
+			step(4, Opcode::MSTORE, 0, 32),
+			None,
+		]
+	);
+
+	// PUSH1 64 MLOAD expands memory without writing to it.
+	let mut infos = StepInfos::default();
+	using(&mut infos, || {
+		run(vec![0x60, 0x40, 0x51], &CONFIG, &mut handler)
+	});
+	assert_eq!(
+		infos.0,
+		vec![
+			step(0, Opcode::PUSH1, 1, 0),
+			step(2, Opcode::MLOAD, 1, 96),
+			None,
+		]
+	);
+}
+
+#[derive(Default)]
+struct Opcodes(Vec<Opcode>);
+
+impl EventListener for Opcodes {
+	fn event(&mut self, event: Event) {
+		if let Event::Step { opcode, .. } = event {
+			self.0.push(opcode);
+		}
+	}
+}
+
+#[test]
+fn step_events_cover_every_opcode_run() {
+	let mut handler = MockHandler::default();
+	let mut opcodes = Opcodes::default();
+
+	// PUSH1 1 PUSH1 0 SSTORE CALLER POP 0xb0 STOP
+	let code = vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x33, 0x50, 0xb0, 0x00];
+	using(&mut opcodes, || run(code, &CONFIG, &mut handler));
+
+	// Opcodes of the core, of the runtime and of the handler alike.
+	assert_eq!(
+		opcodes.0,
+		vec![
+			Opcode::PUSH1,
+			Opcode::PUSH1,
+			Opcode::SSTORE,
+			Opcode::CALLER,
+			Opcode::POP,
+			Opcode(0xb0),
+			Opcode::STOP,
+		]
+	);
+}
+
+#[test]
+fn trace_filter_selects_steps() {
+	let mut handler = MockHandler::default();
+	let mut opcodes = Opcodes::default();
+
+	// PUSH1 1 PUSH1 0 SSTORE PUSH1 0 SLOAD
+	let code = vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x54];
+	let mut runtime = Runtime::new(
+		Shared::new(code),
+		Shared::new(Vec::new()),
+		context(),
+		&CONFIG,
+	);
+	runtime.set_trace_filter(TraceFilter::only(&[Opcode::SSTORE, Opcode::SLOAD]));
+	using(&mut opcodes, || {
+		let _ = runtime.run(&mut handler);
+	});
+
+	assert_eq!(opcodes.0, vec![Opcode::SSTORE, Opcode::SLOAD]);
+	assert!(TraceFilter::default().contains(Opcode::ADD));
+	assert!(!TraceFilter::none().contains(Opcode::ADD));
+}
+
+#[test]
+fn trace_recorder_writes_jsonl() {
+	let mut handler = MockHandler::default();
+	let mut recorder = TraceRecorder::new(1);
+
+	// PUSH1 0x2a PUSH1 0 MSTORE 0xef
+	using(&mut recorder, || {
+		run(
+			vec![0x60, 0x2a, 0x60, 0x00, 0x52, 0xef],
+			&CONFIG,
+			&mut handler,
+		)
+	});
+
+	assert_eq!(
+		recorder.to_jsonl(),
+		concat!(
+			"{\"pc\":0,\"op\":96,\"opName\":\"PUSH1\",\"stack\":[],\"memSize\":0}\n",
+			"{\"pc\":2,\"op\":96,\"opName\":\"PUSH1\",\"stack\":[\"0x2a\"],\"memSize\":0}\n",
+			"{\"pc\":4,\"op\":82,\"opName\":\"MSTORE\",\"stack\":[\"0x0\"],\"memSize\":0}\n",
+			"{\"pc\":5,\"op\":239,\"opName\":\"0xef\",\"stack\":[],\"memSize\":32}\n",
+		)
+	);
+	assert_eq!(recorder.lines()[1].stack, vec![U256::from(0x2a)]);
+}
@@ -1,12 +1,11 @@
 use crate::backend::Backend;
 use crate::gasometer::{self, Gasometer, StorageTarget};
 use crate::{
-	Capture, Config, Context, CreateScheme, ExitError, ExitReason, ExitSucceed, Handler, Opcode,
-	Runtime, Stack, Transfer,
+	AccessList, Capture, Config, Context, CreateScheme, ExitError, ExitReason, ExitSucceed,
+	Handler, MemoryBudget, Opcode, Runtime, Shared, Stack, Transfer,
 };
 use alloc::{
 	collections::{BTreeMap, BTreeSet},
-	rc::Rc,
 	vec::Vec,
 };
 use core::{cmp::min, convert::Infallible};
@@ -311,6 +310,7 @@ pub struct StackExecutor<'config, 'precompiles, S, P> {
 	config: &'config Config,
 	state: S,
 	precompile_set: &'precompiles P,
+	memory_budget: Option<Shared<MemoryBudget>>,
 }
 
 impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
@@ -336,9 +336,16 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
 			config,
 			state,
 			precompile_set,
+			memory_budget: None,
 		}
 	}
 
+	/// Cap the total memory of all frames run by this executor, on top of the
+	/// per-frame memory limit.
+	pub fn set_memory_budget(&mut self, budget: Shared<MemoryBudget>) {
+		self.memory_budget = Some(budget);
+	}
+
 	pub fn state(&self) -> &S {
 		&self.state
 	}
@@ -556,38 +563,18 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
 
 	/// Get the create address from given scheme.
 	pub fn create_address(&self, scheme: CreateScheme) -> H160 {
-		match scheme {
-			CreateScheme::Create2 {
-				caller,
-				code_hash,
-				salt,
-			} => {
-				let mut hasher = Keccak256::new();
-				hasher.input(&[0xff]);
-				hasher.input(&caller[..]);
-				hasher.input(&salt[..]);
-				hasher.input(&code_hash[..]);
-				H256::from_slice(hasher.result().as_slice()).into()
-			}
-			CreateScheme::Legacy { caller } => {
-				let nonce = self.nonce(caller);
-				let mut stream = rlp::RlpStream::new_list(2);
-				stream.append(&caller);
-				stream.append(&nonce);
-				H256::from_slice(Keccak256::digest(&stream.out()).as_slice()).into()
-			}
-			CreateScheme::Fixed(naddress) => naddress,
-		}
+		scheme.address(self)
 	}
 
 	pub fn initialize_with_access_list(&mut self, access_list: Vec<(H160, Vec<H256>)>) {
-		let addresses = access_list.iter().map(|a| a.0);
-		self.state.metadata_mut().access_addresses(addresses);
+		self.prewarm(&access_list.into());
+	}
 
-		let storage_keys = access_list
-			.into_iter()
-			.flat_map(|(address, keys)| keys.into_iter().map(move |key| (address, key)));
-		self.state.metadata_mut().access_storages(storage_keys);
+	fn apply_memory_budget(&self, runtime: &mut Runtime) {
+		if let Some(budget) = &self.memory_budget {
+			// A new runtime has no memory yet, so this always fits.
+			let _ = runtime.set_memory_budget(budget.clone());
+		}
 	}
 
 	fn create_inner(
@@ -610,7 +597,7 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
 
 		fn check_first_byte(config: &Config, code: &[u8]) -> Result<(), ExitError> {
 			if config.disallow_executable_format {
-				if let Some(0xef) = code.get(0) {
+				if let Some(0xef) = code.first() {
 					return Err(ExitError::InvalidCode);
 				}
 			}
@@ -704,11 +691,12 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
 		}
 
 		let mut runtime = Runtime::new(
-			Rc::new(init_code),
-			Rc::new(Vec::new()),
+			Shared::new(init_code),
+			Shared::new(Vec::new()),
 			context,
 			self.config,
 		);
+		self.apply_memory_budget(&mut runtime);
 
 		let reason = self.execute(&mut runtime);
 		log::debug!(target: "evm", "Create execution using address {}: {:?}", address, reason);
@@ -724,27 +712,17 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
 					return Capture::Exit((e.into(), None, Vec::new()));
 				}
 
-				if let Some(limit) = self.config.create_contract_limit {
-					if out.len() > limit {
-						self.state.metadata_mut().gasometer.fail();
-						let _ = self.exit_substate(StackExitKind::Failed);
-						return Capture::Exit((
-							ExitError::CreateContractLimit.into(),
-							None,
-							Vec::new(),
-						));
-					}
+				let out_len = out.len();
+				// Written into the substate, so it is dropped if the deposit fails.
+				if let Err(e) = Handler::set_code(self, address, out) {
+					self.state.metadata_mut().gasometer.fail();
+					let _ = self.exit_substate(StackExitKind::Failed);
+					return Capture::Exit((e.into(), None, Vec::new()));
 				}
 
-				match self
-					.state
-					.metadata_mut()
-					.gasometer
-					.record_deposit(out.len())
-				{
+				match self.state.metadata_mut().gasometer.record_deposit(out_len) {
 					Ok(()) => {
 						let e = self.exit_substate(StackExitKind::Succeeded);
-						self.state.set_code(address, out);
 						try_or_fail!(e);
 						Capture::Exit((ExitReason::Succeed(s), Some(address), Vec::new()))
 					}
@@ -905,7 +883,8 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet>
 			};
 		}
 
-		let mut runtime = Runtime::new(Rc::new(code), Rc::new(input), context, self.config);
+		let mut runtime = Runtime::new(Shared::new(code), Shared::new(input), context, self.config);
+		self.apply_memory_budget(&mut runtime);
 
 		let reason = self.execute(&mut runtime);
 		log::debug!(target: "evm", "Call execution using address {}: {:?}", code_address, reason);
@@ -944,6 +923,10 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 		self.state.basic(address).balance
 	}
 
+	fn nonce(&self, address: H160) -> U256 {
+		self.state.basic(address).nonce
+	}
+
 	fn code_size(&self, address: H160) -> U256 {
 		U256::from(self.state.code(address).len())
 	}
@@ -978,6 +961,10 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 		}
 	}
 
+	fn is_static(&self) -> bool {
+		self.state.metadata().is_static()
+	}
+
 	fn is_cold(&self, address: H160, maybe_index: Option<H256>) -> bool {
 		match maybe_index {
 			None => !self.precompile_set.is_precompile(address) && self.state.is_cold(address),
@@ -985,6 +972,17 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 		}
 	}
 
+	fn warm_address(&mut self, address: H160) {
+		self.state.metadata_mut().access_address(address);
+	}
+
+	fn prewarm(&mut self, list: &AccessList) {
+		self.state.metadata_mut().access_addresses(list.addresses());
+		self.state
+			.metadata_mut()
+			.access_storages(list.storage_keys());
+	}
+
 	fn gas_left(&self) -> U256 {
 		U256::from(self.state.metadata().gasometer.gas())
 	}
@@ -1016,6 +1014,9 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 	fn block_base_fee_per_gas(&self) -> U256 {
 		self.state.block_base_fee_per_gas()
 	}
+	fn supports_base_fee(&self) -> bool {
+		self.config.has_base_fee
+	}
 	fn chain_id(&self) -> U256 {
 		self.state.chain_id()
 	}
@@ -1034,6 +1035,21 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 		Ok(())
 	}
 
+	fn set_code(&mut self, address: H160, code: Vec<u8>) -> Result<(), ExitError> {
+		if let Some(limit) = self.max_code_size() {
+			if code.len() > limit {
+				return Err(ExitError::CreateContractLimit);
+			}
+		}
+
+		self.state.set_code(address, code);
+		Ok(())
+	}
+
+	fn max_code_size(&self) -> Option<usize> {
+		self.config.create_contract_limit
+	}
+
 	fn mark_delete(&mut self, address: H160, target: H160) -> Result<(), ExitError> {
 		let balance = self.balance(address);
 
@@ -1077,6 +1093,7 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 	) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Self::CreateInterrupt> {
 		let capture = self.create_inner(caller, scheme, value, init_code, target_gas, true);
 
+		#[allow(irrefutable_let_patterns)]
 		if let Capture::Exit((ref reason, _, ref return_value)) = capture {
 			emit_exit!(reason, return_value);
 		}
@@ -1127,6 +1144,7 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 			context,
 		);
 
+		#[allow(irrefutable_let_patterns)]
 		if let Capture::Exit((ref reason, ref return_value)) = capture {
 			emit_exit!(reason, return_value);
 		}
@@ -1173,3 +1191,26 @@ impl<'config, 'precompiles, S: StackState<'config>, P: PrecompileSet> Handler
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{Config, StackSubstateMetadata};
+
+	#[test]
+	fn test_static_is_inherited_by_children() {
+		let config = Config::london();
+		let root = StackSubstateMetadata::new(1000, &config);
+		assert!(!root.is_static());
+		assert_eq!(root.depth(), None);
+
+		let child = root.spit_child(100, false);
+		assert!(!child.is_static());
+		assert_eq!(child.depth(), Some(0));
+
+		// A frame below a static one stays static whatever its call asks.
+		let static_child = root.spit_child(100, true);
+		let grandchild = static_child.spit_child(10, false);
+		assert!(grandchild.is_static());
+		assert_eq!(grandchild.depth(), Some(1));
+	}
+}
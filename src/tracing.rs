@@ -1,6 +1,9 @@
 //! Allows to listen to runtime events.
 
 use crate::Context;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 use evm_runtime::{CreateScheme, ExitReason, Transfer};
 use primitive_types::{H160, H256, U256};
 
@@ -70,3 +73,86 @@ pub(crate) fn with<F: FnOnce(&mut (dyn EventListener + 'static))>(f: F) {
 pub fn using<R, F: FnOnce() -> R>(new: &mut (dyn EventListener + 'static), f: F) -> R {
 	listener::using(new, f)
 }
+
+/// Opcode counts of one call frame, see `FrameProfiler`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FrameProfile {
+	/// Call depth of the frame, zero for the frame of the transaction.
+	pub depth: usize,
+	/// Address whose code the frame runs, or the address being created.
+	pub address: H160,
+	/// Number of steps of each opcode, indexed by opcode byte.
+	pub steps: [u64; 256],
+}
+
+/// Counts opcode steps per call frame.
+///
+/// A runtime listener alone sees the steps of every frame of a transaction
+/// in one stream, so counts kept there mix the caller with its callees. This
+/// profiler also listens to executor events, opening a frame on each `Call`
+/// or `Create` and closing it on the matching `Exit`, and charges each step
+/// to the innermost open frame. Run the transaction inside `profile` so both
+/// listeners are installed.
+#[derive(Debug, Clone, Default)]
+pub struct FrameProfiler(Rc<RefCell<Frames>>);
+
+#[derive(Debug, Default)]
+struct Frames {
+	frames: Vec<FrameProfile>,
+	open: Vec<usize>,
+}
+
+impl FrameProfiler {
+	/// Create a profiler with no frames.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Run `f` with this profiler listening to both executor and runtime
+	/// events. Frames are added to those of earlier runs.
+	pub fn profile<R, F: FnOnce() -> R>(&self, f: F) -> R {
+		let mut frames = self.clone();
+		let mut steps = self.clone();
+		using(&mut frames, || evm_runtime::tracing::using(&mut steps, f))
+	}
+
+	/// Profiles of all frames so far, in the order they started.
+	pub fn frames(&self) -> Vec<FrameProfile> {
+		self.0.borrow().frames.clone()
+	}
+}
+
+impl EventListener for FrameProfiler {
+	fn event(&mut self, event: Event) {
+		let address = match event {
+			Event::Call { code_address, .. } => code_address,
+			Event::Create { address, .. } => address,
+			Event::Exit { .. } => {
+				self.0.borrow_mut().open.pop();
+				return;
+			}
+			_ => return,
+		};
+
+		let mut frames = self.0.borrow_mut();
+		let depth = frames.open.len();
+		let index = frames.frames.len();
+		frames.frames.push(FrameProfile {
+			depth,
+			address,
+			steps: [0; 256],
+		});
+		frames.open.push(index);
+	}
+}
+
+impl evm_runtime::tracing::EventListener for FrameProfiler {
+	fn event(&mut self, event: evm_runtime::tracing::Event) {
+		if let evm_runtime::tracing::Event::Step { opcode, .. } = event {
+			let mut frames = self.0.borrow_mut();
+			if let Some(&index) = frames.open.last() {
+				frames.frames[index].steps[opcode.as_usize()] += 1;
+			}
+		}
+	}
+}
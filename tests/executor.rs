@@ -0,0 +1,565 @@
+use evm::backend::{MemoryAccount, MemoryBackend, MemoryVicinity};
+use evm::executor::stack::{
+	MemoryStackState, PrecompileFailure, PrecompileFn, PrecompileOutput, StackExecutor,
+	StackSubstateMetadata,
+};
+use evm::{Config, Context, CreateScheme, ExitReason, ExitSucceed, Handler, MemoryBudget, Shared};
+use primitive_types::{H160, H256, U256};
+use std::collections::BTreeMap;
+
+const CALLER: H160 = H160([0xf0; 20]);
+const ENTRY: H160 = H160([0x10; 20]);
+const MIDDLE: H160 = H160([0x20; 20]);
+const INNER: H160 = H160([0x30; 20]);
+
+fn vicinity() -> MemoryVicinity {
+	MemoryVicinity {
+		gas_price: U256::zero(),
+		origin: CALLER,
+		chain_id: U256::one(),
+		block_hashes: Vec::new(),
+		block_number: U256::zero(),
+		block_coinbase: H160::default(),
+		block_timestamp: U256::zero(),
+		block_difficulty: U256::zero(),
+		block_gas_limit: U256::from(u64::MAX),
+		block_base_fee_per_gas: U256::zero(),
+	}
+}
+
+fn account(code: Vec<u8>) -> MemoryAccount {
+	funded_account(code, 0)
+}
+
+fn funded_account(code: Vec<u8>, balance: u64) -> MemoryAccount {
+	MemoryAccount {
+		nonce: U256::one(),
+		balance: U256::from(balance),
+		storage: BTreeMap::new(),
+		code,
+	}
+}
+
+/// Executor settings for `call_entry` beyond the installed accounts.
+#[derive(Default)]
+struct Options<'a> {
+	/// Memory budget shared between all frames.
+	budget: Option<Shared<MemoryBudget>>,
+	/// Precompiles to install, if any.
+	precompiles: Option<&'a BTreeMap<H160, PrecompileFn>>,
+}
+
+/// Call `ENTRY` with the given accounts installed, returning the exit reason
+/// and the storage of `ENTRY` and `INNER` at slots 0 and 1.
+fn call_entry(
+	accounts: Vec<(H160, MemoryAccount)>,
+	options: Options,
+) -> (ExitReason, [H256; 2], [H256; 2]) {
+	let config = Config::london();
+	let vicinity = vicinity();
+	let state = accounts.into_iter().collect();
+	let backend = MemoryBackend::new(&vicinity, state);
+	let metadata = StackSubstateMetadata::new(1_000_000, &config);
+	let state = MemoryStackState::new(metadata, &backend);
+	let no_precompiles = BTreeMap::new();
+	let precompiles = options.precompiles.unwrap_or(&no_precompiles);
+	let mut executor = StackExecutor::new_with_precompiles(state, &config, precompiles);
+	if let Some(budget) = options.budget {
+		executor.set_memory_budget(budget);
+	}
+
+	let (reason, _) = executor.transact_call(
+		CALLER,
+		ENTRY,
+		U256::zero(),
+		Vec::new(),
+		1_000_000,
+		Vec::new(),
+	);
+	let slots = |address| {
+		[
+			executor.storage(address, H256::from_low_u64_be(0)),
+			executor.storage(address, H256::from_low_u64_be(1)),
+		]
+	};
+	(reason, slots(ENTRY), slots(INNER))
+}
+
+/// Code calling `target` with `opcode` and all gas, then storing the success
+/// flag in slot 0 and the first returned word in slot 1.
+fn caller_of(opcode: u8, target: H160) -> Vec<u8> {
+	caller_with_value(opcode, target, 0)
+}
+
+/// Like `caller_of`, sending `value` with opcodes that take one.
+fn caller_with_value(opcode: u8, target: H160, value: u8) -> Vec<u8> {
+	// PUSH1 32 PUSH1 0 PUSH1 0 PUSH1 0 [PUSH1 value] PUSH20 target GAS <opcode>
+	let mut code = vec![0x60, 0x20, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00];
+	if opcode == 0xf1 || opcode == 0xf2 {
+		code.extend_from_slice(&[0x60, value]);
+	}
+	code.push(0x73);
+	code.extend_from_slice(target.as_bytes());
+	code.extend_from_slice(&[0x5a, opcode]);
+	// PUSH1 0 SSTORE PUSH1 0 MLOAD PUSH1 1 SSTORE
+	code.extend_from_slice(&[0x60, 0x00, 0x55, 0x60, 0x00, 0x51, 0x60, 0x01, 0x55]);
+	code
+}
+
+/// Code calling `target` with `CALL` and returning the success flag.
+fn forwarder_of(target: H160) -> Vec<u8> {
+	// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 target GAS CALL
+	let mut code = vec![
+		0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+	];
+	code.extend_from_slice(target.as_bytes());
+	code.extend_from_slice(&[0x5a, 0xf1]);
+	// PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+	code.extend_from_slice(&[0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+	code
+}
+
+#[test]
+fn call_inside_staticcall_stays_static() {
+	// PUSH1 1 PUSH1 0 SSTORE
+	let store = vec![0x60, 0x01, 0x60, 0x00, 0x55];
+	let one = H256::from_low_u64_be(1);
+	let zero = H256::zero();
+
+	// CALL -> CALL -> SSTORE succeeds all the way down.
+	let (reason, entry, inner) = call_entry(
+		vec![
+			(ENTRY, account(caller_of(0xf1, MIDDLE))),
+			(MIDDLE, account(forwarder_of(INNER))),
+			(INNER, account(store.clone())),
+		],
+		Options::default(),
+	);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry, [one, one]);
+	assert_eq!(inner, [one, zero]);
+
+	// STATICCALL -> CALL -> SSTORE: the middle frame succeeds, but its CALL
+	// is still static, so the store fails.
+	let (reason, entry, inner) = call_entry(
+		vec![
+			(ENTRY, account(caller_of(0xfa, MIDDLE))),
+			(MIDDLE, account(forwarder_of(INNER))),
+			(INNER, account(store)),
+		],
+		Options::default(),
+	);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry, [one, zero]);
+	assert_eq!(inner, [zero, zero]);
+}
+
+#[test]
+fn memory_budget_covers_nested_frames() {
+	// PUSH1 1 PUSH2 0x100 MSTORE: 288 bytes of memory.
+	let inner = vec![0x60, 0x01, 0x61, 0x01, 0x00, 0x52];
+	let accounts = || {
+		vec![
+			(ENTRY, account(caller_of(0xf1, INNER))),
+			(INNER, account(inner.clone())),
+		]
+	};
+	let one = H256::from_low_u64_be(1);
+
+	// Either frame fits on its own, but the entry frame holds 32 bytes while
+	// the inner one runs.
+	let budget = Shared::new(MemoryBudget::new(300));
+	let options = Options {
+		budget: Some(budget.clone()),
+		..Options::default()
+	};
+	let (reason, entry, _) = call_entry(accounts(), options);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry[0], H256::zero());
+	assert_eq!(budget.used(), 0);
+
+	let budget = Shared::new(MemoryBudget::new(320));
+	let options = Options {
+		budget: Some(budget.clone()),
+		..Options::default()
+	};
+	let (reason, entry, _) = call_entry(accounts(), options);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry[0], one);
+	assert_eq!(budget.used(), 0);
+}
+
+#[test]
+fn value_call_without_balance_skips_callee() {
+	// PUSH1 1 PUSH1 0 SSTORE
+	let store = vec![0x60, 0x01, 0x60, 0x00, 0x55];
+	let one = H256::from_low_u64_be(1);
+	let zero = H256::zero();
+
+	for (balance, called) in [(4, zero), (5, one)] {
+		let (reason, entry, inner) = call_entry(
+			vec![
+				(
+					ENTRY,
+					funded_account(caller_with_value(0xf1, INNER, 5), balance),
+				),
+				(INNER, account(store.clone())),
+			],
+			Options::default(),
+		);
+		assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+		assert_eq!(entry[0], called);
+		assert_eq!(inner[0], called);
+	}
+}
+
+#[test]
+fn delegatecall_writes_caller_storage() {
+	// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 INNER GAS DELEGATECALL POP
+	let mut proxy = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73];
+	proxy.extend_from_slice(INNER.as_bytes());
+	proxy.extend_from_slice(&[0x5a, 0xf4, 0x50]);
+	// PUSH1 7 PUSH1 0 SSTORE
+	let store = vec![0x60, 0x07, 0x60, 0x00, 0x55];
+
+	let (reason, entry, inner) = call_entry(
+		vec![(ENTRY, account(proxy)), (INNER, account(store))],
+		Options::default(),
+	);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry, [H256::from_low_u64_be(7), H256::zero()]);
+	assert_eq!(inner, [H256::zero(), H256::zero()]);
+}
+
+#[test]
+fn call_gas_is_capped_to_63_64ths() {
+	// PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 INNER PUSH32 MAX CALL
+	let mut code = vec![
+		0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+	];
+	code.extend_from_slice(INNER.as_bytes());
+	code.push(0x7f);
+	code.extend_from_slice(&[0xff; 32]);
+	// CALL PUSH1 0 SSTORE
+	code.extend_from_slice(&[0xf1, 0x60, 0x00, 0x55]);
+	// GAS PUSH1 0 SSTORE
+	let inner = vec![0x5a, 0x60, 0x00, 0x55];
+
+	let (reason, entry, inner) = call_entry(
+		vec![(ENTRY, account(code)), (INNER, account(inner))],
+		Options::default(),
+	);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry[0], H256::from_low_u64_be(1));
+
+	// 1000000 less the intrinsic 21000, seven pushes and a cold CALL leaves
+	// 976379, of which all but one 64th is forwarded. GAS then costs 2.
+	let forwarded = 976_379 - 976_379 / 64;
+	assert_eq!(inner[0], H256::from_low_u64_be(forwarded - 2));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn frame_profiler_attributes_steps() {
+	use evm::tracing::FrameProfiler;
+	use evm::Opcode;
+
+	// PUSH1 1 PUSH1 0 SSTORE
+	let store = vec![0x60, 0x01, 0x60, 0x00, 0x55];
+	let profiler = FrameProfiler::new();
+	profiler.profile(|| {
+		call_entry(
+			vec![
+				(ENTRY, account(caller_of(0xf1, INNER))),
+				(INNER, account(store)),
+			],
+			Options::default(),
+		)
+	});
+
+	let frames = profiler.frames();
+	assert_eq!(frames.len(), 2);
+	assert_eq!((frames[0].depth, frames[0].address), (0, ENTRY));
+	assert_eq!((frames[1].depth, frames[1].address), (1, INNER));
+	assert_eq!(frames[0].steps[Opcode::CALL.as_usize()], 1);
+	assert_eq!(frames[0].steps[Opcode::SSTORE.as_usize()], 2);
+	assert_eq!(frames[1].steps[Opcode::PUSH1.as_usize()], 2);
+	assert_eq!(frames[1].steps[Opcode::SSTORE.as_usize()], 1);
+	assert_eq!(frames[1].steps.iter().sum::<u64>(), 3);
+}
+
+/// Frame events seen by `trace`, without their borrowed payloads.
+#[cfg(feature = "tracing")]
+#[derive(Debug, PartialEq)]
+enum Traced {
+	Call(H160),
+	Create(H160),
+	Suicide(H160, H160, U256),
+	Exit(ExitReason),
+}
+
+/// Run `f`, returning the frame events the executor emits meanwhile.
+#[cfg(feature = "tracing")]
+fn trace<R>(f: impl FnOnce() -> R) -> Vec<Traced> {
+	use evm::tracing::{using, Event, EventListener};
+
+	struct Recorder(Vec<Traced>);
+
+	impl EventListener for Recorder {
+		fn event(&mut self, event: Event) {
+			self.0.push(match event {
+				Event::Call { code_address, .. } => Traced::Call(code_address),
+				Event::Create { address, .. } => Traced::Create(address),
+				Event::Suicide {
+					address,
+					target,
+					balance,
+				} => Traced::Suicide(address, target, balance),
+				Event::Exit { reason, .. } => Traced::Exit(reason.clone()),
+				_ => return,
+			});
+		}
+	}
+
+	let mut recorder = Recorder(Vec::new());
+	using(&mut recorder, f);
+	recorder.0
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn frame_events_bracket_sub_frames() {
+	let stopped = ExitReason::Succeed(ExitSucceed::Stopped);
+
+	// PUSH1 1 PUSH1 0 SSTORE
+	let store = vec![0x60, 0x01, 0x60, 0x00, 0x55];
+	let events = trace(|| {
+		call_entry(
+			vec![
+				(ENTRY, account(caller_of(0xf1, INNER))),
+				(INNER, account(store)),
+			],
+			Options::default(),
+		)
+	});
+	assert_eq!(
+		events,
+		vec![
+			Traced::Call(ENTRY),
+			Traced::Call(INNER),
+			Traced::Exit(stopped.clone()),
+			Traced::Exit(stopped.clone()),
+		]
+	);
+
+	let created =
+		H160::from_slice(&hex::decode("323be5326396e88520dc02c763bd2bcaf6a856a1").unwrap());
+	let events = trace(|| deployed_code(1_000_000));
+	assert_eq!(
+		events,
+		vec![
+			Traced::Call(ENTRY),
+			Traced::Create(created),
+			Traced::Exit(ExitReason::Succeed(ExitSucceed::Returned)),
+			Traced::Exit(stopped),
+		]
+	);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn suicide_event_carries_balance() {
+	// PUSH20 INNER SELFDESTRUCT
+	let mut code = vec![0x73];
+	code.extend_from_slice(INNER.as_bytes());
+	code.push(0xff);
+
+	let events = trace(|| {
+		call_entry(
+			vec![(ENTRY, funded_account(code, 1000))],
+			Options::default(),
+		)
+	});
+	assert_eq!(
+		events,
+		vec![
+			Traced::Call(ENTRY),
+			Traced::Suicide(ENTRY, INNER, U256::from(1000)),
+			Traced::Exit(ExitReason::Succeed(ExitSucceed::Suicided)),
+		]
+	);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn gas_is_recorded_once_per_opcode() {
+	use evm::gasometer::tracing::{using, Event, EventListener};
+
+	#[derive(Debug, PartialEq)]
+	enum Charge {
+		Static(u64),
+		Dynamic { gas_cost: u64, memory_gas: u64 },
+	}
+
+	struct Charges(Vec<Charge>);
+
+	impl EventListener for Charges {
+		fn event(&mut self, event: Event) {
+			match event {
+				Event::RecordCost { cost, .. } => self.0.push(Charge::Static(cost)),
+				Event::RecordDynamicCost {
+					gas_cost,
+					memory_gas,
+					..
+				} => self.0.push(Charge::Dynamic {
+					gas_cost,
+					memory_gas,
+				}),
+				_ => (),
+			}
+		}
+	}
+
+	// PUSH1 1 PUSH1 2 ADD PUSH1 0 MSTORE STOP
+	let code = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x60, 0x00, 0x52, 0x00];
+	let mut charges = Charges(Vec::new());
+	using(&mut charges, || {
+		call_entry(vec![(ENTRY, account(code))], Options::default())
+	});
+
+	// The frame first takes the gas left after the intrinsic cost. Then each
+	// opcode is charged once, in order, before it runs, so the gas of an
+	// opcode is the charge at its step.
+	assert_eq!(
+		charges.0,
+		vec![
+			Charge::Static(1_000_000 - 21_000),
+			Charge::Static(3),
+			Charge::Static(3),
+			Charge::Static(3),
+			Charge::Static(3),
+			Charge::Dynamic {
+				gas_cost: 3,
+				memory_gas: 3,
+			},
+			Charge::Static(0),
+		]
+	);
+}
+
+fn seven(
+	_input: &[u8],
+	_gas: Option<u64>,
+	_context: &Context,
+	_is_static: bool,
+) -> Result<PrecompileOutput, PrecompileFailure> {
+	Ok(PrecompileOutput {
+		exit_status: ExitSucceed::Returned,
+		cost: 0,
+		output: H256::from_low_u64_be(7).as_bytes().to_vec(),
+		logs: Vec::new(),
+	})
+}
+
+#[test]
+fn missing_precompile_is_empty_account() {
+	let mut precompiles = BTreeMap::new();
+	precompiles.insert(H160::from_low_u64_be(1), seven as PrecompileFn);
+	let options = || Options {
+		precompiles: Some(&precompiles),
+		..Options::default()
+	};
+	let one = H256::from_low_u64_be(1);
+
+	let (reason, entry, _) = call_entry(
+		vec![(ENTRY, account(caller_of(0xf1, H160::from_low_u64_be(1))))],
+		options(),
+	);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry, [one, H256::from_low_u64_be(7)]);
+
+	// 0x0a is in the precompile range but not in the set, so the call succeeds
+	// with nothing returned, like any call to an empty account.
+	let (reason, entry, _) = call_entry(
+		vec![(ENTRY, account(caller_of(0xf1, H160::from_low_u64_be(10))))],
+		options(),
+	);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(entry, [one, H256::zero()]);
+}
+
+#[test]
+fn legacy_create_address_uses_account_nonce() {
+	let caller =
+		H160::from_slice(&hex::decode("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0").unwrap());
+	let config = Config::london();
+	let vicinity = vicinity();
+
+	for (nonce, expected) in [
+		(0, "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"),
+		(1, "343c43a37d37dff08ae8c4a11544c718abb4fcf8"),
+		(2, "f778b86fa74e846c4f0a1fbd1335fe81c00a0c91"),
+		(3, "fffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c"),
+	] {
+		let mut state = BTreeMap::new();
+		state.insert(
+			caller,
+			MemoryAccount {
+				nonce: U256::from(nonce),
+				..account(Vec::new())
+			},
+		);
+		let backend = MemoryBackend::new(&vicinity, state);
+		let metadata = StackSubstateMetadata::new(1_000_000, &config);
+		let executor = StackExecutor::new_with_precompiles(
+			MemoryStackState::new(metadata, &backend),
+			&config,
+			&(),
+		);
+		assert_eq!(
+			executor.create_address(CreateScheme::Legacy { caller }),
+			H160::from_slice(&hex::decode(expected).unwrap())
+		);
+	}
+}
+
+/// Code deployed by `ENTRY` creating a contract whose init code returns 256
+/// zero bytes, in a transaction with `gas_limit`.
+fn deployed_code(gas_limit: u64) -> (ExitReason, Vec<u8>) {
+	// PUSH6 (PUSH2 0x100 PUSH1 0 RETURN) PUSH1 0 MSTORE
+	let mut code = vec![0x65, 0x61, 0x01, 0x00, 0x60, 0x00, 0xf3, 0x60, 0x00, 0x52];
+	// PUSH1 6 PUSH1 26 PUSH1 0 CREATE
+	code.extend_from_slice(&[0x60, 0x06, 0x60, 0x1a, 0x60, 0x00, 0xf0]);
+
+	let config = Config::london();
+	let vicinity = vicinity();
+	let state = vec![(ENTRY, account(code))].into_iter().collect();
+	let backend = MemoryBackend::new(&vicinity, state);
+	let metadata = StackSubstateMetadata::new(gas_limit, &config);
+	let state = MemoryStackState::new(metadata, &backend);
+	let mut executor = StackExecutor::new_with_precompiles(state, &config, &());
+	let created = executor.create_address(CreateScheme::Legacy { caller: ENTRY });
+
+	let (reason, _) = executor.transact_call(
+		CALLER,
+		ENTRY,
+		U256::zero(),
+		Vec::new(),
+		gas_limit,
+		Vec::new(),
+	);
+	(reason, executor.code(created))
+}
+
+#[test]
+fn failed_code_deposit_leaves_no_code() {
+	let (reason, code) = deployed_code(1_000_000);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert_eq!(code, vec![0; 256]);
+
+	// The code is set before the 51200 gas deposit is charged, and must be
+	// dropped with the create's substate when that fails.
+	let (reason, code) = deployed_code(100_000);
+	assert_eq!(reason, ExitReason::Succeed(ExitSucceed::Stopped));
+	assert!(code.is_empty());
+}